@@ -0,0 +1,20 @@
+use std::process::Command;
+
+//Records the git revision of the build for the results files
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    if let Ok(head) = std::fs::read_to_string(".git/HEAD") {
+        if let Some(r) = head.trim().strip_prefix("ref: ") {
+            println!("cargo:rerun-if-changed=.git/{}", r);
+        }
+    }
+    let revision = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| String::from_utf8(o.stdout).ok());
+    if let Some(r) = revision {
+        println!("cargo:rustc-env=GIT_REVISION={}", r.trim());
+    }
+}
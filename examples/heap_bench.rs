@@ -0,0 +1,43 @@
+//! Compares the scan of `part_min` with the heap of `part_min_heap`
+//!
+//! Run with `cargo run -r --example heap_bench -- [n] [m] [t] [d]`, by default
+//! on 10^5 points and 200 random halfspaces in the plane split into 64 parts.
+
+use partitions_rs::algos::{part_min, part_min_heap, RunReport};
+use partitions_rs::intersections;
+use partitions_rs::ss::SetSystem;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+fn main() {
+    let args: Vec<usize> = std::env::args()
+        .skip(1)
+        .map(|x| x.parse().expect("Arguments are integers"))
+        .collect();
+    let arg = |i: usize, default: usize| args.get(i).copied().unwrap_or(default);
+    let (n, m, t, d) = (arg(0, 100_000), arg(1, 200), arg(2, 64), arg(3, 2));
+
+    let mut rng = StdRng::seed_from_u64(0);
+    let ss = SetSystem::rhs(n as i32, m as i32, d, &mut rng);
+    let sizes = vec![n / t; t - 1]
+        .into_iter()
+        .chain([n - (t - 1) * (n / t)])
+        .collect::<Vec<usize>>();
+    println!("n = {}, m = {}, t = {}, d = {}", n, m, t, d);
+
+    let print = |name: &str, parts: &SetSystem, run: &RunReport| {
+        let max = intersections(&parts.sets, &ss.sets).into_iter().max();
+        println!(
+            "{:>8}: adjacency {:.3?}, partition {:.3?}, max crossings {}",
+            name,
+            run.adjacency_time,
+            run.partition_time,
+            max.unwrap_or(0)
+        );
+    };
+    let (parts, run) = part_min(&ss, &sizes, &mut StdRng::seed_from_u64(1)).expect("Valid sizes");
+    print("scan", &parts, &run);
+    let (parts, run) =
+        part_min_heap(&ss, &sizes, &mut StdRng::seed_from_u64(1)).expect("Valid sizes");
+    print("heap", &parts, &run);
+}
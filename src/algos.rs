@@ -1,23 +1,328 @@
+use crate::bitset::{BitSet, WORD_BITS};
+use crate::heap::IndexedHeap;
+use crate::incidence::Incidence;
+use crate::partition::{Metadata, Partition, PartitionError};
 use crate::ss::{Point, Set, SetSystem};
 use indicatif::ProgressBar;
-use rand::Rng;
+use rand::{Rng, RngCore};
 use rayon::prelude::*;
+use std::fs;
 use std::time::{Duration, Instant};
 
+/// Statistics of a run of one of the algorithms
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunReport {
+    /// Time spent building the incidence of the sets, zero for the parallel
+    /// algorithms which read the bit sets of the sets directly
+    pub adjacency_time: Duration,
+    /// Time spent building the parts
+    pub partition_time: Duration,
+    /// Peak resident memory of the process in bytes, `None` where it cannot
+    /// be measured
+    pub peak_memory: Option<u64>,
+    /// Number of times the weight of a set was doubled
+    pub weight_doublings: u64,
+    /// Exponent `w` of the heaviest set weight `2^w` at the end of the run
+    pub max_set_weight: u32,
+    /// Number of warmup rounds the algorithm ran, if it uses some
+    pub warmup: Option<i32>,
+}
+
+/// Peak resident set size of the process, read from `/proc/self/status`
+fn peak_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Sizes of the parts built by the algorithms
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartSizes {
+    /// `t - 1` parts of `n / t` points, the remaining points going to the
+    /// last part
+    Remainder,
+    /// Parts of `ceil(n / t)` points followed by parts of `floor(n / t)`
+    /// points
+    Balanced,
+    /// The given sizes, whose number must be `t` and sum must be `n`
+    Custom(Vec<usize>),
+}
+
+impl PartSizes {
+    /// Size of each of the `t` parts of a partition of `n` points
+    pub fn sizes(&self, n: usize, t: usize) -> Result<Vec<usize>, PartitionError> {
+        let res = match self {
+            PartSizes::Custom(sizes) => sizes.clone(),
+            _ if t == 0 => Vec::new(),
+            PartSizes::Remainder => {
+                let mut res = vec![n / t; t - 1];
+                res.push(n - (t - 1) * (n / t));
+                res
+            }
+            PartSizes::Balanced => (0..t).map(|i| n / t + usize::from(i < n % t)).collect(),
+        };
+        if res.len() != t || res.is_empty() {
+            return Err(PartitionError::PartCount {
+                expected: t.max(1),
+                found: res.len(),
+            });
+        }
+        if res.iter().sum::<usize>() != n {
+            return Err(PartitionError::PointCount {
+                expected: n,
+                found: res.iter().sum(),
+            });
+        }
+        match res.iter().position(|x| *x == 0) {
+            Some(part) => Err(PartitionError::EmptyPart { part }),
+            None => Ok(res),
+        }
+    }
+}
+
+/// Options shared by the partitioning algorithms
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionOptions {
+    pub sizes: PartSizes,
+    /// Number of warmup rounds, for the algorithms that use some
+    pub warmup: Option<i32>,
+}
+
+impl Default for PartitionOptions {
+    fn default() -> PartitionOptions {
+        PartitionOptions {
+            sizes: PartSizes::Remainder,
+            warmup: None,
+        }
+    }
+}
+
+/// Algorithm building a partition of the points of a set system
+pub trait Partitioner: Sync {
+    /// Name of the algorithm, recorded in partition and results files
+    fn name(&self) -> &'static str;
+
+    /// Other names the algorithm can be selected by
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Partition of the points of `ss` into `t` parts
+    fn partition(
+        &self,
+        ss: &SetSystem,
+        t: usize,
+        options: &PartitionOptions,
+        rng: &mut dyn RngCore,
+    ) -> Result<(Partition, RunReport), PartitionError>;
+}
+
+/// Partition file of the parts returned by an algorithm
+fn labelled(name: &str, ss: &SetSystem, parts: &SetSystem) -> Partition {
+    let mut res = Partition::from_parts(&parts.sets).expect("Algorithms return a partition");
+    res.metadata = Metadata {
+        algorithm: Some(name.to_string()),
+        seed: None,
+        source: Some(ss.fingerprint()),
+    };
+    res
+}
+
+/// Greedy algorithm adding the point of minimum weight to the current part,
+/// see `part_min`
+pub struct Min;
+
+/// Greedy algorithm stopping the scan at the first point keeping the
+/// potential under its bound, see `part_potential`
+pub struct Potential;
+
+/// Algorithm building each part at once from estimated distances, see
+/// `part_at_once`
+pub struct AtOnce;
+
+impl Partitioner for Min {
+    fn name(&self) -> &'static str {
+        "min"
+    }
+
+    fn partition(
+        &self,
+        ss: &SetSystem,
+        t: usize,
+        options: &PartitionOptions,
+        rng: &mut dyn RngCore,
+    ) -> Result<(Partition, RunReport), PartitionError> {
+        let sizes = options.sizes.sizes(ss.points.len(), t)?;
+        let (parts, report) = part_min(ss, &sizes, rng)?;
+        Ok((labelled(self.name(), ss, &parts), report))
+    }
+}
+
+impl Partitioner for Potential {
+    fn name(&self) -> &'static str {
+        "potential"
+    }
+
+    fn partition(
+        &self,
+        ss: &SetSystem,
+        t: usize,
+        options: &PartitionOptions,
+        rng: &mut dyn RngCore,
+    ) -> Result<(Partition, RunReport), PartitionError> {
+        let sizes = options.sizes.sizes(ss.points.len(), t)?;
+        let (parts, report) = part_potential(ss, &sizes, rng)?;
+        Ok((labelled(self.name(), ss, &parts), report))
+    }
+}
+
+impl Partitioner for AtOnce {
+    fn name(&self) -> &'static str {
+        "atonce"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["ao"]
+    }
+
+    /// Uses `max(t, 30)` warmup rounds unless given in the options
+    fn partition(
+        &self,
+        ss: &SetSystem,
+        t: usize,
+        options: &PartitionOptions,
+        rng: &mut dyn RngCore,
+    ) -> Result<(Partition, RunReport), PartitionError> {
+        let sizes = options.sizes.sizes(ss.points.len(), t)?;
+        let warmup = options.warmup.unwrap_or((t as i32).max(30));
+        let (parts, report) = part_at_once(ss, &sizes, warmup, rng)?;
+        Ok((labelled(self.name(), ss, &parts), report))
+    }
+}
+
+/// Version of `Min` selecting points from a heap, see `part_min_heap`
+pub struct HeapMin;
+
+/// Parallel version of `Min`, see `part_min_par`
+pub struct ParallelMin;
+
+/// Parallel version of `Potential`, see `part_potential_par`
+pub struct ParallelPotential;
+
+impl Partitioner for HeapMin {
+    fn name(&self) -> &'static str {
+        "min-heap"
+    }
+
+    fn partition(
+        &self,
+        ss: &SetSystem,
+        t: usize,
+        options: &PartitionOptions,
+        rng: &mut dyn RngCore,
+    ) -> Result<(Partition, RunReport), PartitionError> {
+        let sizes = options.sizes.sizes(ss.points.len(), t)?;
+        let (parts, report) = part_min_heap(ss, &sizes, rng)?;
+        Ok((labelled(self.name(), ss, &parts), report))
+    }
+}
+
+impl Partitioner for ParallelMin {
+    fn name(&self) -> &'static str {
+        "min-par"
+    }
+
+    fn partition(
+        &self,
+        ss: &SetSystem,
+        t: usize,
+        options: &PartitionOptions,
+        rng: &mut dyn RngCore,
+    ) -> Result<(Partition, RunReport), PartitionError> {
+        let sizes = options.sizes.sizes(ss.points.len(), t)?;
+        let (parts, report) = part_min_par(ss, &sizes, rng)?;
+        Ok((labelled(self.name(), ss, &parts), report))
+    }
+}
+
+impl Partitioner for ParallelPotential {
+    fn name(&self) -> &'static str {
+        "potential-par"
+    }
+
+    fn partition(
+        &self,
+        ss: &SetSystem,
+        t: usize,
+        options: &PartitionOptions,
+        rng: &mut dyn RngCore,
+    ) -> Result<(Partition, RunReport), PartitionError> {
+        let sizes = options.sizes.sizes(ss.points.len(), t)?;
+        let (parts, report) = part_potential_par(ss, &sizes, rng)?;
+        Ok((labelled(self.name(), ss, &parts), report))
+    }
+}
+
+/// All the available algorithms
+pub fn partitioners() -> Vec<Box<dyn Partitioner>> {
+    vec![
+        Box::new(Min),
+        Box::new(AtOnce),
+        Box::new(Potential),
+        Box::new(HeapMin),
+        Box::new(ParallelMin),
+        Box::new(ParallelPotential),
+    ]
+}
+
+/// Algorithm called `name` or one of its aliases
+pub fn partitioner(name: &str) -> Option<Box<dyn Partitioner>> {
+    partitioners()
+        .into_iter()
+        .find(|p| p.name() == name || p.aliases().contains(&name))
+}
+
 pub fn intersects((i, j): (usize, usize), s: &Set) -> bool {
     s.points[i] != s.points[j]
 }
 
-pub fn part_min(ss: &SetSystem, t: i32) -> (SetSystem, Duration) {
+/// Weight `2^w` of a set crossed `w` times, divided by `2^scale`
+///
+/// Weights are only ever compared or summed with weights sharing the same
+/// `scale`, usually the current maximum, so they stay representable however
+/// many times a set is doubled. Sets much lighter than the heaviest one
+/// underflow to 0, as they would be negligible anyway.
+fn scaled_weight(w: u32, scale: u32) -> f64 {
+    f64::exp2(w as f64 - scale as f64)
+}
+
+fn max_weight(set_weight: &[u32]) -> u32 {
+    set_weight.iter().copied().max().unwrap_or(0)
+}
+
+/// Checks that `sizes` are the sizes of the parts of a partition of the
+/// points of `ss`, none of them empty
+fn check_sizes(ss: &SetSystem, sizes: &[usize]) -> Result<(), PartitionError> {
+    PartSizes::Custom(sizes.to_vec())
+        .sizes(ss.points.len(), sizes.len())
+        .map(|_| ())
+}
+
+pub fn part_min<R: Rng + ?Sized>(
+    ss: &SetSystem,
+    sizes: &[usize],
+    rng: &mut R,
+) -> Result<(SetSystem, RunReport), PartitionError> {
+    check_sizes(ss, sizes)?;
     //SetSystem constants
     let n = ss.points.len();
     let m = ss.sets.len();
-    let mut rng = rand::thread_rng();
+    let t = sizes.len();
 
     let now = Instant::now();
-    let (_pin, _pout, sin, sout) = ss.build_adjacency();
-    let elapsed = now.elapsed();
-    println!("Building adjacency took: {:.3?}", elapsed);
+    let incidence = Incidence::new(ss);
+    let adjacency_time = now.elapsed();
 
     let now = Instant::now();
     //Build result points and sets vectors
@@ -29,14 +334,18 @@ pub fn part_min(ss: &SetSystem, t: i32) -> (SetSystem, Duration) {
 
     //List all points not yet in  a part
     let mut available_pts: Vec<bool> = vec![true; n];
-    let mut pt_weight: Vec<u128> = vec![0; n];
+    let mut pt_weight: Vec<f64> = vec![0.; n];
     let mut set_weight: Vec<u32> = vec![0; m];
+    let mut weight_doublings: u64 = 0;
 
     let bar = ProgressBar::new(t as u64);
     //Part building
-    for i in 0..t - 1 {
+    for (i, size) in sizes[..t - 1].iter().enumerate() {
         bar.inc(1);
-        let mut part: Vec<bool> = vec![false; n];
+        let mut part = BitSet::new(n);
+
+        //All weights of this part are relative to the heaviest set
+        let scale = max_weight(&set_weight);
 
         //sets_weight to normalize in the potential function
         //let mut sets_weight: u128 = 0;
@@ -51,99 +360,95 @@ pub fn part_min(ss: &SetSystem, t: i32) -> (SetSystem, Duration) {
         for i in available_pts.iter().enumerate() {
             if let (j, true) = i {
                 temp.push(j);
-                pt_weight[j] = 0;
+                pt_weight[j] = 0.;
             }
         }
         let start = temp[rng.gen_range(0..temp.len())];
-        part[start] = true;
+        part.insert(start);
         available_pts[start] = false;
-        for j in 0..m {
-            if ss.sets[j].points[start] {
-                for k in sout[j].iter() {
-                    pt_weight[*k] += 1 << set_weight[j];
-                }
-            } else {
-                for k in sin[j].iter() {
-                    pt_weight[*k] += 1 << set_weight[j];
-                }
+        for (j, w) in set_weight.iter().enumerate() {
+            for k in incidence.side(j, !ss.sets[j].points[start]) {
+                pt_weight[k] += scaled_weight(*w, scale);
             }
         }
-        for _ in 1..(n as i32 / t) {
+        for _ in 1..*size {
             let mut min = n + 1;
             for l in 0..n {
-                if available_pts[l] {
-                    if min == n + 1 || pt_weight[l] < pt_weight[min] {
-                        min = l;
-                    } else if min == n + 1
-                        || pt_weight[l] == pt_weight[min] && rng.gen::<f32>() > 0.5
-                    {
-                        min = l;
-                    }
+                if available_pts[l]
+                    && (min == n + 1
+                        || pt_weight[l] < pt_weight[min]
+                        || pt_weight[l] == pt_weight[min] && rng.gen::<f32>() > 0.5)
+                {
+                    min = l;
                 }
             }
-            part[min] = true;
+            part.insert(min);
             available_pts[min] = false;
 
             for j in 0..m {
                 if !intersect_part[j] && intersects((start, min), &ss.sets[j]) {
-                    if ss.sets[j].points[start] {
-                        for x in sout[j].iter() {
-                            if available_pts[*x] && intersects((start, *x), &ss.sets[j]) {
-                                pt_weight[*x] -= 1 << set_weight[j];
-                            }
-                        }
-                    } else {
-                        for x in sin[j].iter() {
-                            if available_pts[*x] && intersects((start, *x), &ss.sets[j]) {
-                                pt_weight[*x] -= 1 << set_weight[j];
-                            }
+                    for x in incidence.side(j, !ss.sets[j].points[start]) {
+                        if available_pts[x] {
+                            pt_weight[x] -= scaled_weight(set_weight[j], scale);
                         }
                     }
                     intersect_part[j] = true;
                     set_weight[j] += 1;
+                    weight_doublings += 1;
                 }
             }
         }
         res_sets.push(Set {
             points: part,
-            index: (i + 1) as usize,
+            index: i + 1,
         });
     }
     bar.inc(1);
-    let mut part: Vec<bool> = vec![false; n];
+    let mut part = BitSet::new(n);
     for x in available_pts.iter().enumerate() {
         if let (p, true) = x {
-            part[p] = true
+            part.insert(p)
         }
     }
     res_sets.push(Set {
         points: part,
-        index: t as usize,
+        index: t,
     });
     bar.finish();
 
-    let elapsed = now.elapsed();
-    println!("Elapsed: {:.3?}", elapsed);
-    (
+    let partition_time = now.elapsed();
+    Ok((
         SetSystem {
             points: res_points,
             sets: res_sets,
+            ranges: Vec::new(),
         },
-        elapsed,
-    )
+        RunReport {
+            adjacency_time,
+            partition_time,
+            peak_memory: peak_memory(),
+            weight_doublings,
+            max_set_weight: max_weight(&set_weight),
+            warmup: None,
+        },
+    ))
 }
 
-pub fn part_potential(ss: &SetSystem, t: i32) -> (SetSystem, Duration) {
+pub fn part_potential<R: Rng + ?Sized>(
+    ss: &SetSystem,
+    sizes: &[usize],
+    rng: &mut R,
+) -> Result<(SetSystem, RunReport), PartitionError> {
+    check_sizes(ss, sizes)?;
     //SetSystem constants
     let n = ss.points.len();
     let m = ss.sets.len();
+    let t = sizes.len();
     let d = ss.points[0].coordinates.len();
-    let mut rng = rand::thread_rng();
 
     let now = Instant::now();
-    let (_pin, _pout, sin, sout) = ss.build_adjacency();
-    let elapsed = now.elapsed();
-    println!("Building adjacency took: {:.3?}", elapsed);
+    let incidence = Incidence::new(ss);
+    let adjacency_time = now.elapsed();
 
     let now = Instant::now();
     //Build result points and sets vectors
@@ -155,19 +460,25 @@ pub fn part_potential(ss: &SetSystem, t: i32) -> (SetSystem, Duration) {
 
     //List all points not yet in  a part
     let mut available_pts: Vec<bool> = vec![true; n];
-    let mut pt_weight: Vec<u128> = vec![0; n];
+    let mut pt_weight: Vec<f64> = vec![0.; n];
     let mut set_weight: Vec<u32> = vec![0; m];
+    let mut weight_doublings: u64 = 0;
 
     let bar = ProgressBar::new(t as u64);
     //Part building
-    for i in 0..t - 1 {
+    for (i, size) in sizes[..t - 1].iter().enumerate() {
         bar.inc(1);
-        let mut part: Vec<bool> = vec![false; n];
+        let mut part = BitSet::new(n);
+        //Points not yet in a part, for the stop bound
+        let remaining: usize = sizes[i..].iter().sum();
+
+        //All weights of this part are relative to the heaviest set
+        let scale = max_weight(&set_weight);
 
         //sets_weight to normalize in the potential function
-        let mut sets_weight: u128 = 0;
-        for j in 1..m {
-            sets_weight += 1 << set_weight[j];
+        let mut sets_weight: f64 = 0.;
+        for w in set_weight.iter().skip(1) {
+            sets_weight += scaled_weight(*w, scale);
         }
 
         //let mut part_weight: u128 = 0;
@@ -177,110 +488,105 @@ pub fn part_potential(ss: &SetSystem, t: i32) -> (SetSystem, Duration) {
         for i in available_pts.iter().enumerate() {
             if let (j, true) = i {
                 temp.push(j);
-                pt_weight[j] = 0;
+                pt_weight[j] = 0.;
             }
         }
         let start = temp[rng.gen_range(0..temp.len())];
-        part[start] = true;
+        part.insert(start);
         available_pts[start] = false;
-        for j in 0..m {
-            if ss.sets[j].points[start] {
-                for k in sout[j].iter() {
-                    pt_weight[*k] += 1 << set_weight[j];
-                }
-            } else {
-                for k in sin[j].iter() {
-                    pt_weight[*k] += 1 << set_weight[j];
-                }
+        for (j, w) in set_weight.iter().enumerate() {
+            for k in incidence.side(j, !ss.sets[j].points[start]) {
+                pt_weight[k] += scaled_weight(*w, scale);
             }
         }
-        let mut part_weight = 0;
-        for p in 1..(n as i32 / t) {
+        let mut part_weight = 0.;
+        for p in 1..*size {
             let mut min = n + 1;
             for l in 0..n {
                 if available_pts[l] {
-                    if ((part_weight as f64) + pt_weight[l] as f64)
-                        * f64::powf(
-                            (n as f64) - (i as f64) * (n as f64) / (t as f64),
-                            1.0 / (d as f64),
-                        )
-                        / sets_weight as f64
+                    if (part_weight + pt_weight[l]) * f64::powf(remaining as f64, 1.0 / (d as f64))
+                        / sets_weight
                         <= 2.0 * f64::powf(p as f64, 1.0 / (d as f64))
                     {
                         min = l;
                         break;
                     }
-                    if min == n + 1 || pt_weight[l] < pt_weight[min] {
-                        min = l;
-                    } else if min == n + 1
+                    if min == n + 1
+                        || pt_weight[l] < pt_weight[min]
                         || pt_weight[l] == pt_weight[min] && rng.gen::<f32>() > 0.5
                     {
                         min = l;
                     }
                 }
             }
-            part[min] = true;
+            part.insert(min);
             available_pts[min] = false;
             part_weight += pt_weight[min];
 
             for j in 0..m {
                 if !intersect_part[j] && intersects((start, min), &ss.sets[j]) {
-                    if ss.sets[j].points[start] {
-                        for x in sout[j].iter() {
-                            if available_pts[*x] && intersects((start, *x), &ss.sets[j]) {
-                                pt_weight[*x] -= 1 << set_weight[j];
-                            }
-                        }
-                    } else {
-                        for x in sin[j].iter() {
-                            if available_pts[*x] && intersects((start, *x), &ss.sets[j]) {
-                                pt_weight[*x] -= 1 << set_weight[j];
-                            }
+                    for x in incidence.side(j, !ss.sets[j].points[start]) {
+                        if available_pts[x] {
+                            pt_weight[x] -= scaled_weight(set_weight[j], scale);
                         }
                     }
                     intersect_part[j] = true;
                     set_weight[j] += 1;
+                    weight_doublings += 1;
                 }
             }
         }
         res_sets.push(Set {
             points: part,
-            index: (i + 1) as usize,
+            index: i + 1,
         });
     }
     bar.inc(1);
-    let mut part: Vec<bool> = vec![false; n];
+    let mut part = BitSet::new(n);
     for x in available_pts.iter().enumerate() {
         if let (p, true) = x {
-            part[p] = true
+            part.insert(p)
         }
     }
     res_sets.push(Set {
         points: part,
-        index: t as usize,
+        index: t,
     });
     bar.finish();
 
-    let elapsed = now.elapsed();
-    println!("Elapsed: {:.3?}", elapsed);
-    (
+    let partition_time = now.elapsed();
+    Ok((
         SetSystem {
             points: res_points,
             sets: res_sets,
+            ranges: Vec::new(),
         },
-        elapsed,
-    )
+        RunReport {
+            adjacency_time,
+            partition_time,
+            peak_memory: peak_memory(),
+            weight_doublings,
+            max_set_weight: max_weight(&set_weight),
+            warmup: None,
+        },
+    ))
 }
-pub fn part_at_once(ss: &SetSystem, t: i32, k: i32) -> (SetSystem, Duration) {
+
+pub fn part_at_once<R: Rng + ?Sized>(
+    ss: &SetSystem,
+    sizes: &[usize],
+    k: i32,
+    rng: &mut R,
+) -> Result<(SetSystem, RunReport), PartitionError> {
+    check_sizes(ss, sizes)?;
     //SetSystem constants
     let n = ss.points.len();
     let m = ss.sets.len();
-    let mut rng = rand::thread_rng();
+    let t = sizes.len();
 
     let now = Instant::now();
-    let (_pin, _pout, sin, sout) = ss.build_adjacency();
-    let elapsed = now.elapsed();
-    println!("Building adjacency took: {:.3?}", elapsed);
+    let incidence = Incidence::new(ss);
+    let adjacency_time = now.elapsed();
 
     let now = Instant::now();
     //Build result points and sets vectors
@@ -293,12 +599,13 @@ pub fn part_at_once(ss: &SetSystem, t: i32, k: i32) -> (SetSystem, Duration) {
     //List all points not yet in  a part
     let mut available_pts: Vec<bool> = vec![true; n];
     let mut set_weight: Vec<u32> = vec![0; m];
+    let mut weight_doublings: u64 = 0;
 
     let bar = ProgressBar::new(t as u64);
     //Part building
-    for i in 0..t - 1 {
+    for (i, size) in sizes[..t - 1].iter().enumerate() {
         bar.inc(1);
-        let mut part: Vec<bool> = vec![false; n];
+        let mut part = BitSet::new(n);
 
         let mut temp: Vec<usize> = Vec::new();
         for l in available_pts.iter().enumerate() {
@@ -307,157 +614,532 @@ pub fn part_at_once(ss: &SetSystem, t: i32, k: i32) -> (SetSystem, Duration) {
             }
         }
         let start = temp[rng.gen_range(0..temp.len())];
-        part[start] = true;
+        part.insert(start);
         available_pts[start] = false;
-        let distances = distance(&ss, &available_pts, start, k, &set_weight, &sin, &sout);
-        let mut tosort: Vec<(usize, &u64)> = Vec::new();
+        let distances = distance(ss, &available_pts, start, k, &set_weight, &incidence, rng);
+        let mut tosort: Vec<(usize, &f64)> = Vec::new();
         for x in distances.iter().enumerate() {
             if available_pts[x.0] {
                 tosort.push(x);
             }
         }
-        tosort.sort_by(|a, b| a.1.cmp(&b.1));
-        for l in 0..(n as i32 / t - 1) as usize {
-            part[tosort[l].0] = true;
+        tosort.sort_by(|a, b| a.1.total_cmp(b.1));
+        for l in 0..size - 1 {
+            part.insert(tosort[l].0);
             available_pts[tosort[l].0] = false;
         }
-        set_weight = (0..m)
+        let updated: Vec<u32> = (0..m)
             .into_par_iter()
             //.into_iter()
-            .map(|j| {
-                update_weight(
-                    &ss.sets[j],
-                    &sout[j],
-                    &sin[j],
-                    set_weight[j],
-                    &tosort,
-                    n,
-                    t,
-                    start,
-                )
-            })
+            .map(|j| update_weight(&ss.sets[j], set_weight[j], &tosort, *size, start))
             .collect();
+        weight_doublings += updated
+            .iter()
+            .zip(set_weight.iter())
+            .map(|(u, w)| (u - w) as u64)
+            .sum::<u64>();
+        set_weight = updated;
         res_sets.push(Set {
             points: part,
-            index: (i + 1) as usize,
+            index: i + 1,
         });
     }
     bar.inc(1);
-    let mut part: Vec<bool> = vec![false; n];
+    let mut part = BitSet::new(n);
     for x in available_pts.iter().enumerate() {
         if let (p, true) = x {
-            part[p] = true
+            part.insert(p)
         }
     }
     res_sets.push(Set {
         points: part,
-        index: t as usize,
+        index: t,
     });
     bar.finish();
 
-    let elapsed = now.elapsed();
-    println!("Elapsed: {:.3?}", elapsed);
-    (
+    let partition_time = now.elapsed();
+    Ok((
         SetSystem {
             points: res_points,
             sets: res_sets,
+            ranges: Vec::new(),
+        },
+        RunReport {
+            adjacency_time,
+            partition_time,
+            peak_memory: peak_memory(),
+            weight_doublings,
+            max_set_weight: max_weight(&set_weight),
+            warmup: Some(k),
         },
-        elapsed,
-    )
+    ))
 }
 
-fn update_weight(
-    s: &Set,
-    sout: &Vec<usize>,
-    sin: &Vec<usize>,
-    initial_weight: u32,
-    tosort: &Vec<(usize, &u64)>,
-    n: usize,
-    t: i32,
-    start: usize,
-) -> u32 {
-    if s.points[start] {
-        for l in (0..=((n as i32 / t) - 2) as usize).rev() {
-            for k in sout.iter() {
-                if *k == tosort[l].0 {
-                    return initial_weight + 1;
+/// Version of `part_min` keeping the available points in an indexed heap
+/// keyed by their weight, so that every point is added in logarithmic time
+/// instead of a scan of all the points
+///
+/// Ties are broken by random keys drawn for every part.
+pub fn part_min_heap<R: Rng + ?Sized>(
+    ss: &SetSystem,
+    sizes: &[usize],
+    rng: &mut R,
+) -> Result<(SetSystem, RunReport), PartitionError> {
+    check_sizes(ss, sizes)?;
+    //SetSystem constants
+    let n = ss.points.len();
+    let m = ss.sets.len();
+    let t = sizes.len();
+
+    let now = Instant::now();
+    let incidence = Incidence::new(ss);
+    let adjacency_time = now.elapsed();
+
+    let now = Instant::now();
+    let mut res_sets: Vec<Set> = Vec::new();
+    let res_points: Vec<Point> = ss.points.clone();
+
+    //List all points not yet in  a part
+    let mut available_pts: Vec<bool> = vec![true; n];
+    let mut pt_weight: Vec<f64> = vec![0.; n];
+    let mut set_weight: Vec<u32> = vec![0; m];
+    let mut weight_doublings: u64 = 0;
+    let mut heap: IndexedHeap<(f64, u64)> = IndexedHeap::new(n);
+
+    let bar = ProgressBar::new(t as u64);
+    //Part building
+    for (i, size) in sizes[..t - 1].iter().enumerate() {
+        bar.inc(1);
+        let mut part = BitSet::new(n);
+
+        //All weights of this part are relative to the heaviest set
+        let scale = max_weight(&set_weight);
+        let mut intersect_part: Vec<bool> = vec![false; m];
+        let ties: Vec<u64> = (0..n).map(|_| rng.gen()).collect();
+
+        let temp: Vec<usize> = (0..n).filter(|j| available_pts[*j]).collect();
+        for j in temp.iter() {
+            pt_weight[*j] = 0.;
+        }
+        let start = temp[rng.gen_range(0..temp.len())];
+        part.insert(start);
+        available_pts[start] = false;
+        for (j, w) in set_weight.iter().enumerate() {
+            for k in incidence.side(j, !ss.sets[j].points[start]) {
+                pt_weight[k] += scaled_weight(*w, scale);
+            }
+        }
+        //The available points with their weights for this part
+        heap.rebuild(
+            temp.iter()
+                .filter(|j| available_pts[**j])
+                .map(|j| (*j, (pt_weight[*j], ties[*j]))),
+        );
+        for _ in 1..*size {
+            let (min, _) = heap
+                .pop()
+                .expect("Parts are not larger than the available points");
+            part.insert(min);
+            available_pts[min] = false;
+
+            for j in 0..m {
+                if !intersect_part[j] && intersects((start, min), &ss.sets[j]) {
+                    for x in incidence.side(j, !ss.sets[j].points[start]) {
+                        if available_pts[x] {
+                            pt_weight[x] -= scaled_weight(set_weight[j], scale);
+                            heap.decrease_key(x, (pt_weight[x], ties[x]));
+                        }
+                    }
+                    intersect_part[j] = true;
+                    set_weight[j] += 1;
+                    weight_doublings += 1;
                 }
             }
         }
-    } else {
-        for l in (0..=((n as i32 / t) - 2) as usize).rev() {
-            for k in sin.iter() {
-                if *k == tosort[l].0 {
-                    return initial_weight + 1;
+        res_sets.push(Set {
+            points: part,
+            index: i + 1,
+        });
+    }
+    bar.inc(1);
+    let mut part = BitSet::new(n);
+    for x in available_pts.iter().enumerate() {
+        if let (p, true) = x {
+            part.insert(p)
+        }
+    }
+    res_sets.push(Set {
+        points: part,
+        index: t,
+    });
+    bar.finish();
+
+    let partition_time = now.elapsed();
+    Ok((
+        SetSystem {
+            points: res_points,
+            sets: res_sets,
+            ranges: Vec::new(),
+        },
+        RunReport {
+            adjacency_time,
+            partition_time,
+            peak_memory: peak_memory(),
+            weight_doublings,
+            max_set_weight: max_weight(&set_weight),
+            warmup: None,
+        },
+    ))
+}
+
+/// Parallel version of `part_min`
+///
+/// The point of minimum weight is found by a parallel reduction, ties being
+/// broken by random keys drawn for every part so that the result only
+/// depends on the seed and not on the number of threads. The weights are
+/// computed in parallel over the words of the bit sets of the sets, without
+/// building an incidence.
+pub fn part_min_par<R: Rng + ?Sized>(
+    ss: &SetSystem,
+    sizes: &[usize],
+    rng: &mut R,
+) -> Result<(SetSystem, RunReport), PartitionError> {
+    greedy_par(ss, sizes, false, rng)
+}
+
+/// Parallel version of `part_potential`, see `part_min_par`
+///
+/// The scan stops at the first point, in index order, keeping the potential
+/// under its bound, as in the sequential version.
+pub fn part_potential_par<R: Rng + ?Sized>(
+    ss: &SetSystem,
+    sizes: &[usize],
+    rng: &mut R,
+) -> Result<(SetSystem, RunReport), PartitionError> {
+    greedy_par(ss, sizes, true, rng)
+}
+
+fn greedy_par<R: Rng + ?Sized>(
+    ss: &SetSystem,
+    sizes: &[usize],
+    potential: bool,
+    rng: &mut R,
+) -> Result<(SetSystem, RunReport), PartitionError> {
+    check_sizes(ss, sizes)?;
+    //SetSystem constants
+    let n = ss.points.len();
+    let m = ss.sets.len();
+    let t = sizes.len();
+    let d = ss.points[0].coordinates.len();
+
+    let now = Instant::now();
+    let mut res_sets: Vec<Set> = Vec::new();
+    let res_points: Vec<Point> = ss.points.clone();
+
+    //List all points not yet in  a part
+    let mut available_pts: Vec<bool> = vec![true; n];
+    let mut pt_weight: Vec<f64> = vec![0.; n];
+    let mut set_weight: Vec<u32> = vec![0; m];
+    let mut weight_doublings: u64 = 0;
+
+    let bar = ProgressBar::new(t as u64);
+    //Part building
+    for (i, size) in sizes[..t - 1].iter().enumerate() {
+        bar.inc(1);
+        let mut part = BitSet::new(n);
+        //Points not yet in a part, for the stop bound
+        let remaining: usize = sizes[i..].iter().sum();
+
+        //All weights of this part are relative to the heaviest set
+        let scale = max_weight(&set_weight);
+        let sets_weight: f64 = set_weight
+            .iter()
+            .skip(1)
+            .map(|w| scaled_weight(*w, scale))
+            .sum();
+        let mut intersect_part: Vec<bool> = vec![false; m];
+        let ties: Vec<u64> = (0..n).map(|_| rng.gen()).collect();
+
+        let temp: Vec<usize> = (0..n).filter(|j| available_pts[*j]).collect();
+        let start = temp[rng.gen_range(0..temp.len())];
+        part.insert(start);
+        available_pts[start] = false;
+        //Weights of the points of every word, separated from start by the
+        //set when their bit differs from that of start
+        pt_weight
+            .par_chunks_mut(WORD_BITS)
+            .enumerate()
+            .for_each(|(k, weights)| {
+                weights.fill(0.);
+                for (s, w) in ss.sets.iter().zip(set_weight.iter()) {
+                    let flip = if s.points[start] { !0 } else { 0 };
+                    let mut word = s.points.words()[k] ^ flip;
+                    while word != 0 {
+                        let b = word.trailing_zeros() as usize;
+                        word &= word - 1;
+                        if b < weights.len() {
+                            weights[b] += scaled_weight(*w, scale);
+                        }
+                    }
                 }
+            });
+        let mut part_weight = 0.;
+        for p in 1..*size {
+            let below_bound = |l: usize| {
+                (part_weight + pt_weight[l]) * f64::powf(remaining as f64, 1.0 / (d as f64))
+                    / sets_weight
+                    <= 2.0 * f64::powf(p as f64, 1.0 / (d as f64))
+            };
+            let first = if potential {
+                (0..n)
+                    .into_par_iter()
+                    .find_first(|l| available_pts[*l] && below_bound(*l))
+            } else {
+                None
+            };
+            let min = first
+                .or_else(|| {
+                    (0..n)
+                        .into_par_iter()
+                        .filter(|l| available_pts[*l])
+                        .min_by(|a, b| {
+                            pt_weight[*a]
+                                .total_cmp(&pt_weight[*b])
+                                .then(ties[*a].cmp(&ties[*b]))
+                        })
+                })
+                .expect("Parts are not larger than the available points");
+            part.insert(min);
+            available_pts[min] = false;
+            part_weight += pt_weight[min];
+
+            let crossed: Vec<usize> = (0..m)
+                .into_par_iter()
+                .filter(|j| !intersect_part[*j])
+                .filter(|j| ss.sets[*j].points[start] != ss.sets[*j].points[min])
+                .collect();
+            pt_weight
+                .par_chunks_mut(WORD_BITS)
+                .enumerate()
+                .for_each(|(k, weights)| {
+                    for j in crossed.iter() {
+                        let s = &ss.sets[*j].points;
+                        let flip = if s[start] { !0 } else { 0 };
+                        let mut word = s.words()[k] ^ flip;
+                        while word != 0 {
+                            let b = word.trailing_zeros() as usize;
+                            word &= word - 1;
+                            if b < weights.len() && available_pts[k * WORD_BITS + b] {
+                                weights[b] -= scaled_weight(set_weight[*j], scale);
+                            }
+                        }
+                    }
+                });
+            for j in crossed.iter() {
+                intersect_part[*j] = true;
+                set_weight[*j] += 1;
             }
+            weight_doublings += crossed.len() as u64;
         }
+        res_sets.push(Set {
+            points: part,
+            index: i + 1,
+        });
+    }
+    bar.inc(1);
+    let mut part = BitSet::new(n);
+    for x in available_pts.iter().enumerate() {
+        if let (p, true) = x {
+            part.insert(p)
+        }
+    }
+    res_sets.push(Set {
+        points: part,
+        index: t,
+    });
+    bar.finish();
+
+    let partition_time = now.elapsed();
+    Ok((
+        SetSystem {
+            points: res_points,
+            sets: res_sets,
+            ranges: Vec::new(),
+        },
+        RunReport {
+            adjacency_time: Duration::ZERO,
+            partition_time,
+            peak_memory: peak_memory(),
+            weight_doublings,
+            max_set_weight: max_weight(&set_weight),
+            warmup: None,
+        },
+    ))
+}
+
+/// Weight of `s` after adding to the part of `start` the first `size - 1`
+/// points of `tosort`, doubled if one of them is not on the side of `start`
+fn update_weight(
+    s: &Set,
+    initial_weight: u32,
+    tosort: &[(usize, &f64)],
+    size: usize,
+    start: usize,
+) -> u32 {
+    if tosort[..size - 1]
+        .iter()
+        .any(|(x, _)| s.points[*x] != s.points[start])
+    {
+        initial_weight + 1
+    } else {
+        initial_weight
     }
-    initial_weight
 }
 
-fn distance(
+fn distance<R: Rng + ?Sized>(
     ss: &SetSystem,
-    available: &Vec<bool>,
+    available: &[bool],
     start: usize,
     k: i32,
-    sets_weight: &Vec<u32>,
-    sin: &Vec<Vec<usize>>,
-    sout: &Vec<Vec<usize>>,
-) -> Vec<u64> {
+    sets_weight: &[u32],
+    incidence: &Incidence,
+    rng: &mut R,
+) -> Vec<f64> {
     let n = ss.points.len();
-    let mut res = vec![0; n];
+    let scale = max_weight(sets_weight);
+    let mut res = vec![0.; n];
     for i in 0..n {
         if available[i] {
-            res[i] = 1;
+            res[i] = scaled_weight(0, scale);
         }
     }
-    let mut limit = 0;
-    let range = (k as f32).log2() as u32;
-    if !*sets_weight.iter().max().expect("No max") <= range {
-        limit = *sets_weight.iter().max().expect("No max") - range;
-    }
     for _ in 0..k {
-        let s = exponential_pick(sets_weight, range);
-        if sets_weight[s] >= limit {
-            if ss.sets[s].points[start] {
-                for i in sout[s].iter() {
-                    if available[*i] {
-                        res[*i] += 1 << (sets_weight[s] - limit);
-                    }
-                }
-            } else {
-                for i in sin[s].iter() {
-                    if available[*i] {
-                        res[*i] += 1 << (sets_weight[s] - limit);
-                    }
-                }
+        let Some(s) = exponential_pick(sets_weight, scale, rng) else {
+            break;
+        };
+        for i in incidence.side(s, !ss.sets[s].points[start]) {
+            if available[i] {
+                res[i] += scaled_weight(sets_weight[s], scale);
             }
         }
     }
     res
 }
 
-fn exponential_pick(w: &Vec<u32>, range: u32) -> usize {
-    let mut total: u64 = 0;
-    let mut rng = rand::thread_rng();
-    let mut limit = 0;
-    if !*w.iter().max().expect("No max") <= range {
-        limit = *w.iter().max().expect("No max") - range;
+/// Picks a set with probability proportional to its weight, `None` if there
+/// are no sets
+fn exponential_pick<R: Rng + ?Sized>(w: &[u32], scale: u32, rng: &mut R) -> Option<usize> {
+    if w.is_empty() {
+        return None;
     }
-    for i in 0..w.len() {
-        if limit <= w[i] {
-            total += 1 << (w[i] - limit);
+    let total: f64 = w.iter().map(|x| scaled_weight(*x, scale)).sum();
+    let stop_at = rng.gen_range(0.0..total);
+    let mut partial_sum = 0.;
+    let mut i: usize = 0;
+    while i + 1 < w.len() && partial_sum + scaled_weight(w[i], scale) < stop_at {
+        partial_sum += scaled_weight(w[i], scale);
+        i += 1;
+    }
+    Some(i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    type AlgoResult = Result<(SetSystem, RunReport), PartitionError>;
+
+    #[test]
+    fn part_sizes_sum_to_the_points() {
+        assert_eq!(PartSizes::Remainder.sizes(10, 3), Ok(vec![3, 3, 4]));
+        assert_eq!(PartSizes::Balanced.sizes(10, 3), Ok(vec![4, 3, 3]));
+        assert_eq!(PartSizes::Balanced.sizes(9, 3), Ok(vec![3, 3, 3]));
+        let custom = PartSizes::Custom(vec![5, 1, 4]);
+        assert_eq!(custom.sizes(10, 3), Ok(vec![5, 1, 4]));
+        assert_eq!(
+            custom.sizes(10, 2),
+            Err(PartitionError::PartCount {
+                expected: 2,
+                found: 3
+            })
+        );
+        assert_eq!(
+            custom.sizes(11, 3),
+            Err(PartitionError::PointCount {
+                expected: 11,
+                found: 10
+            })
+        );
+        assert_eq!(
+            PartSizes::Custom(vec![5, 0, 5]).sizes(10, 3),
+            Err(PartitionError::EmptyPart { part: 1 })
+        );
+        assert_eq!(
+            PartSizes::Remainder.sizes(10, 0),
+            Err(PartitionError::PartCount {
+                expected: 1,
+                found: 0
+            })
+        );
+        //Fewer points than parts leave some parts empty
+        assert_eq!(
+            PartSizes::Remainder.sizes(3, 5),
+            Err(PartitionError::EmptyPart { part: 0 })
+        );
+        assert_eq!(
+            PartSizes::Balanced.sizes(3, 5),
+            Err(PartitionError::EmptyPart { part: 3 })
+        );
+    }
+
+    /// Points of the parts built by `algorithm` in a pool of `threads`
+    /// threads, for a fixed seed
+    fn parts_with_threads(
+        algorithm: fn(&SetSystem, &[usize], &mut StdRng) -> AlgoResult,
+        ss: &SetSystem,
+        sizes: &[usize],
+        threads: usize,
+    ) -> Vec<BitSet> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .expect("Fail to build thread pool");
+        let mut rng = StdRng::seed_from_u64(5);
+        let (parts, _) = pool
+            .install(|| algorithm(ss, sizes, &mut rng))
+            .expect("Valid part sizes");
+        parts.sets.into_iter().map(|s| s.points).collect()
+    }
+
+    #[test]
+    fn parallel_algorithms_do_not_depend_on_the_threads() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let ss = SetSystem::rhs(300, 200, 2, &mut rng);
+        let sizes = PartSizes::Remainder
+            .sizes(ss.points.len(), 8)
+            .expect("Valid part sizes");
+        for algorithm in [part_min_par, part_potential_par] {
+            assert_eq!(
+                parts_with_threads(algorithm, &ss, &sizes, 1),
+                parts_with_threads(algorithm, &ss, &sizes, 4)
+            );
         }
     }
-    let stop_at = rng.gen_range(0..total);
-    let mut partial_sum = 0;
-    let mut i: usize = 0;
-    while i < w.len() && (limit > w[i] || (partial_sum + (1 << (w[i] - limit))) < stop_at) {
-        if limit <= w[i] {
-            partial_sum += 1 << (w[i] - limit);
+
+    #[test]
+    fn partitioners_handle_set_systems_without_sets() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let ss = SetSystem::rhs(40, 0, 2, &mut rng);
+        assert!(ss.sets.is_empty());
+        for p in partitioners() {
+            let (partition, _) = p
+                .partition(&ss, 4, &PartitionOptions::default(), &mut rng)
+                .expect("Valid part sizes");
+            let parts = partition.parts().expect("Labels in 0..t");
+            let sizes: Vec<usize> = parts.iter().map(|s| s.points.count_ones()).collect();
+            assert_eq!(sizes, vec![10; 4], "{}", p.name());
         }
-        i += 1;
     }
-    i
 }
@@ -0,0 +1,166 @@
+//! Binary set system format
+//!
+//! All integers are little endian. The file starts with a header:
+//!
+//! | bytes | content                                |
+//! |-------|----------------------------------------|
+//! | 4     | magic `PRSS`                           |
+//! | 2     | format version (1)                     |
+//! | 1     | coordinate type (0 for `f32`)          |
+//! | 1     | reserved, 0                            |
+//! | 8     | number of points `n`                   |
+//! | 8     | number of sets `m`                     |
+//! | 4     | dimension `d`                          |
+//!
+//! followed by the `n * d` coordinates, point after point, and by the `m`
+//! sets, each as `ceil(n / 64)` 64 bit words where bit `i % 64` of word
+//! `i / 64` tells whether point `i` is in the set. Ranges are written as the
+//! sets of their points.
+
+use crate::bitset::BitSet;
+use crate::ss::{ParseError, Point, Set, SetSystem};
+
+pub const MAGIC: &[u8; 4] = b"PRSS";
+pub const VERSION: u16 = 1;
+/// Coordinate type tag for `f32` coordinates
+pub const COORD_F32: u8 = 0;
+
+pub fn write(ss: &SetSystem) -> Vec<u8> {
+    let n = ss.points.len();
+    let d = ss.points.first().map_or(0, |p| p.coordinates.len());
+    let m = ss.sets.len() + ss.ranges.len();
+    let mut res = Vec::with_capacity(28 + 4 * n * d + 8 * m * n.div_ceil(64));
+    res.extend_from_slice(MAGIC);
+    res.extend_from_slice(&VERSION.to_le_bytes());
+    res.push(COORD_F32);
+    res.push(0);
+    res.extend_from_slice(&(n as u64).to_le_bytes());
+    res.extend_from_slice(&(m as u64).to_le_bytes());
+    res.extend_from_slice(&(d as u32).to_le_bytes());
+    for p in ss.points.iter() {
+        for c in p.coordinates.iter() {
+            res.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+    for s in ss.sets.iter() {
+        for w in s.points.words() {
+            res.extend_from_slice(&w.to_le_bytes());
+        }
+    }
+    for r in ss.ranges.iter() {
+        for w in r.to_bitset(&ss.points).words() {
+            res.extend_from_slice(&w.to_le_bytes());
+        }
+    }
+    res
+}
+
+pub fn read(bytes: &[u8]) -> Result<SetSystem, ParseError> {
+    let mut r = Reader { bytes, pos: 0 };
+    if r.take(4)? != MAGIC {
+        return Err(ParseError::BadMagic);
+    }
+    let version = u16::from_le_bytes(r.array()?);
+    if version != VERSION {
+        return Err(ParseError::UnsupportedVersion(version));
+    }
+    let coord = r.take(2)?[0];
+    if coord != COORD_F32 {
+        return Err(ParseError::UnsupportedCoordinates(coord));
+    }
+    let n = u64::from_le_bytes(r.array()?) as usize;
+    let m = u64::from_le_bytes(r.array()?) as usize;
+    let d = u32::from_le_bytes(r.array()?) as usize;
+    //Check the size upfront rather than failing after large allocations
+    let words = n.div_ceil(64);
+    let expected = n
+        .checked_mul(d)
+        .and_then(|x| x.checked_mul(4))
+        .zip(m.checked_mul(words).and_then(|x| x.checked_mul(8)))
+        .and_then(|(a, b)| a.checked_add(b));
+    if expected != Some(bytes.len() - r.pos) {
+        return Err(ParseError::Truncated);
+    }
+    let mut points = Vec::with_capacity(n);
+    for index in 0..n {
+        let mut coordinates = Vec::with_capacity(d);
+        for _ in 0..d {
+            coordinates.push(f32::from_le_bytes(r.array()?));
+        }
+        points.push(Point { coordinates, index });
+    }
+    let mut sets = Vec::with_capacity(m);
+    for index in 0..m {
+        let mut row = Vec::with_capacity(words);
+        for _ in 0..words {
+            row.push(u64::from_le_bytes(r.array()?));
+        }
+        sets.push(Set {
+            points: BitSet::from_words(row, n),
+            index,
+        });
+    }
+    Ok(SetSystem {
+        points,
+        sets,
+        ranges: Vec::new(),
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, k: usize) -> Result<&'a [u8], ParseError> {
+        let res = self
+            .bytes
+            .get(self.pos..self.pos + k)
+            .ok_or(ParseError::Truncated)?;
+        self.pos += k;
+        Ok(res)
+    }
+
+    fn array<const K: usize>(&mut self) -> Result<[u8; K], ParseError> {
+        Ok(self.take(K)?.try_into().expect("slice of length K"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ss::BallCentre;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn round_trip_materializes_the_ranges() {
+        let mut rng = StdRng::seed_from_u64(5);
+        //Point counts around word boundaries
+        for n in [0, 1, 63, 64, 65, 130] {
+            let ss = SetSystem::ball_ranges(n, 12, 3, None, BallCentre::Point, &mut rng);
+            let res = read(&write(&ss)).expect("Written set systems can be read");
+            assert!(res.ranges.is_empty());
+            assert_eq!(res.points.len(), ss.points.len());
+            for (a, b) in res.points.iter().zip(ss.points.iter()) {
+                assert_eq!(a.coordinates, b.coordinates);
+            }
+            let sets: Vec<&BitSet> = res.sets.iter().map(|s| &s.points).collect();
+            let ranges: Vec<BitSet> = ss.ranges.iter().map(|r| r.to_bitset(&ss.points)).collect();
+            assert_eq!(sets, ranges.iter().collect::<Vec<&BitSet>>());
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let bytes = write(&SetSystem::rhs(70, 6, 2, &mut rng));
+        for len in [3, 27, bytes.len() - 1] {
+            assert!(read(&bytes[..len]).is_err(), "{} bytes", len);
+        }
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert!(matches!(read(&longer), Err(ParseError::Truncated)));
+    }
+}
@@ -0,0 +1,141 @@
+use std::ops::Index;
+
+/// Number of indices packed in a word
+pub const WORD_BITS: usize = 64;
+
+/// Fixed length set of indices packed in 64 bit words
+///
+/// Bits past `len` are always zero so that word-wise operations never need
+/// to mask the last word of the left operand.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    /// Empty set over the indices `0..len`
+    pub fn new(len: usize) -> BitSet {
+        BitSet {
+            words: vec![0; len.div_ceil(WORD_BITS)],
+            len,
+        }
+    }
+
+    /// Set over `0..len` whose bit `i % 64` of word `i / 64` tells whether
+    /// `i` is in the set, bits past `len` being ignored
+    pub fn from_words(mut words: Vec<u64>, len: usize) -> BitSet {
+        words.resize(len.div_ceil(WORD_BITS), 0);
+        if !len.is_multiple_of(WORD_BITS) {
+            if let Some(last) = words.last_mut() {
+                *last &= (1 << (len % WORD_BITS)) - 1;
+            }
+        }
+        BitSet { words, len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    pub fn contains(&self, i: usize) -> bool {
+        self.words[i / WORD_BITS] >> (i % WORD_BITS) & 1 == 1
+    }
+
+    pub fn insert(&mut self, i: usize) {
+        assert!(i < self.len, "index {} out of bounds ({})", i, self.len);
+        self.words[i / WORD_BITS] |= 1 << (i % WORD_BITS);
+    }
+
+    pub fn remove(&mut self, i: usize) {
+        assert!(i < self.len, "index {} out of bounds ({})", i, self.len);
+        self.words[i / WORD_BITS] &= !(1 << (i % WORD_BITS));
+    }
+
+    /// Indices of `0..len` not in the set
+    pub fn complement(&self) -> BitSet {
+        BitSet::from_words(self.words.iter().map(|w| !w).collect(), self.len)
+    }
+
+    /// Number of indices in the set
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Membership of every index in `0..len`
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(|i| self.contains(i))
+    }
+
+    /// Indices in the set, in increasing order
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        ones(self.words.iter().copied())
+    }
+
+    /// Whether `other` contains some but not all of the indices of `self`
+    pub fn crosses(&self, other: &BitSet) -> bool {
+        let mut inside = 0;
+        let mut outside = 0;
+        for (a, b) in self.words.iter().zip(other.words.iter()) {
+            inside |= a & b;
+            outside |= a & !b;
+            if inside != 0 && outside != 0 {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Positions of the set bits of consecutive words
+fn ones(words: impl Iterator<Item = u64>) -> impl Iterator<Item = usize> {
+    words.enumerate().flat_map(|(k, mut w)| {
+        std::iter::from_fn(move || {
+            if w == 0 {
+                None
+            } else {
+                let b = w.trailing_zeros() as usize;
+                w &= w - 1;
+                Some(k * WORD_BITS + b)
+            }
+        })
+    })
+}
+
+impl Index<usize> for BitSet {
+    type Output = bool;
+
+    fn index(&self, i: usize) -> &bool {
+        assert!(i < self.len, "index {} out of bounds ({})", i, self.len);
+        if self.contains(i) {
+            &true
+        } else {
+            &false
+        }
+    }
+}
+
+impl FromIterator<bool> for BitSet {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> BitSet {
+        let mut words = Vec::new();
+        let mut len = 0;
+        for b in iter {
+            if len % WORD_BITS == 0 {
+                words.push(0);
+            }
+            if b {
+                words[len / WORD_BITS] |= 1 << (len % WORD_BITS);
+            }
+            len += 1;
+        }
+        BitSet { words, len }
+    }
+}
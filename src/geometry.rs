@@ -0,0 +1,108 @@
+/// Tolerance under which a value is considered to be zero
+const EPSILON: f64 = 1e-9;
+
+/// Hyperplane `{x : normal . x = offset}` with a unit normal
+#[derive(Debug, Clone)]
+pub struct Hyperplane {
+    pub normal: Vec<f64>,
+    pub offset: f64,
+}
+
+impl Hyperplane {
+    /// Hyperplane through `d` points of dimension `d`, `None` if the points
+    /// are affinely dependent
+    pub fn through(points: &[&[f32]]) -> Option<Hyperplane> {
+        let d = points.len();
+        let origin = points.first()?;
+        //Rows spanning the hyperplane, reduced to echelon form
+        let mut rows: Vec<Vec<f64>> = points[1..]
+            .iter()
+            .map(|p| (0..d).map(|k| (p[k] - origin[k]) as f64).collect())
+            .collect();
+        let mut pivots: Vec<usize> = Vec::new();
+        let mut free = None;
+        for c in 0..d {
+            let r = pivots.len();
+            let best =
+                (r..rows.len()).max_by(|a, b| rows[*a][c].abs().total_cmp(&rows[*b][c].abs()));
+            match best {
+                Some(b) if rows[b][c].abs() > EPSILON => {
+                    rows.swap(r, b);
+                    let pivot = rows[r][c];
+                    for x in rows[r].iter_mut() {
+                        *x /= pivot;
+                    }
+                    let pivot_row = rows[r].clone();
+                    for (i, row) in rows.iter_mut().enumerate() {
+                        if i != r && row[c] != 0. {
+                            let factor = row[c];
+                            for (x, y) in row.iter_mut().zip(pivot_row.iter()) {
+                                *x -= factor * y;
+                            }
+                        }
+                    }
+                    pivots.push(c);
+                }
+                _ => {
+                    if free.is_some() {
+                        return None;
+                    }
+                    free = Some(c);
+                }
+            }
+        }
+        //The normal spans the kernel of the reduced rows
+        let free = free?;
+        let mut normal = vec![0.; d];
+        normal[free] = 1.;
+        for (r, c) in pivots.iter().enumerate() {
+            normal[*c] = -rows[r][free];
+        }
+        let norm = normal.iter().map(|x| x * x).sum::<f64>().sqrt();
+        for x in normal.iter_mut() {
+            *x /= norm;
+        }
+        let offset = dot(&normal, origin);
+        Some(Hyperplane { normal, offset })
+    }
+
+    /// Signed distance from `x` to the hyperplane
+    pub fn eval(&self, x: &[f32]) -> f64 {
+        dot(&self.normal, x) - self.offset
+    }
+
+    /// Side of the hyperplane `x` lies on: 1 above, -1 below and 0 on it
+    pub fn side(&self, x: &[f32]) -> i8 {
+        let v = self.eval(x);
+        if v > EPSILON {
+            1
+        } else if v < -EPSILON {
+            -1
+        } else {
+            0
+        }
+    }
+}
+
+fn dot(a: &[f64], x: &[f32]) -> f64 {
+    a.iter().zip(x.iter()).map(|(a, x)| a * *x as f64).sum()
+}
+
+/// All `k`-combinations of `0..n` in lexicographic order
+pub fn combinations(n: usize, k: usize) -> impl Iterator<Item = Vec<usize>> {
+    let mut current: Option<Vec<usize>> = if k <= n { Some((0..k).collect()) } else { None };
+    std::iter::from_fn(move || {
+        let res = current.clone()?;
+        let c = current.as_mut()?;
+        match (0..k).rev().find(|i| c[*i] < n - k + i) {
+            Some(i) => {
+                c[i] += 1;
+                for j in i + 1..k {
+                    c[j] = c[j - 1] + 1;
+                }
+            }
+            None => current = None,
+        }
+        Some(res)
+    })
+}
@@ -0,0 +1,206 @@
+/// Binary min-heap over the items `0..n`, supporting changes of the key of
+/// an item already in the heap
+#[derive(Debug, Clone)]
+pub struct IndexedHeap<K> {
+    /// Items in heap order
+    heap: Vec<usize>,
+    /// Position of every item in `heap`, `None` if it is not in the heap
+    positions: Vec<Option<usize>>,
+    keys: Vec<Option<K>>,
+}
+
+impl<K: PartialOrd + Copy> IndexedHeap<K> {
+    /// Empty heap over the items `0..n`
+    pub fn new(n: usize) -> IndexedHeap<K> {
+        IndexedHeap {
+            heap: Vec::with_capacity(n),
+            positions: vec![None; n],
+            keys: vec![None; n],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn contains(&self, i: usize) -> bool {
+        self.positions[i].is_some()
+    }
+
+    /// Key of `i` if it is in the heap
+    pub fn key(&self, i: usize) -> Option<K> {
+        self.positions[i].and(self.keys[i])
+    }
+
+    /// Item of minimum key, without removing it
+    pub fn peek(&self) -> Option<(usize, K)> {
+        let i = *self.heap.first()?;
+        Some((i, self.keys[i].expect("Items in the heap have a key")))
+    }
+
+    /// Inserts `i` with `key`, or updates its key if it is already there
+    pub fn push(&mut self, i: usize, key: K) {
+        match self.positions[i] {
+            Some(_) => self.update(i, key),
+            None => {
+                self.keys[i] = Some(key);
+                self.positions[i] = Some(self.heap.len());
+                self.heap.push(i);
+                self.sift_up(self.heap.len() - 1);
+            }
+        }
+    }
+
+    /// Replaces the items of the heap by `items`, in time linear in the
+    /// number of items removed and inserted
+    pub fn rebuild(&mut self, items: impl IntoIterator<Item = (usize, K)>) {
+        for i in self.heap.drain(..) {
+            self.positions[i] = None;
+        }
+        for (i, key) in items {
+            assert!(self.positions[i].is_none(), "Item {} given twice", i);
+            self.keys[i] = Some(key);
+            self.positions[i] = Some(self.heap.len());
+            self.heap.push(i);
+        }
+        //Bottom-up heap construction, from the last parent to the root
+        for p in (0..self.heap.len() / 2).rev() {
+            self.sift_down(p);
+        }
+    }
+
+    /// Removes and returns the item of minimum key
+    pub fn pop(&mut self) -> Option<(usize, K)> {
+        let res = self.peek()?;
+        self.remove_at(0);
+        Some(res)
+    }
+
+    /// Removes `i` from the heap if it is there
+    pub fn remove(&mut self, i: usize) {
+        if let Some(p) = self.positions[i] {
+            self.remove_at(p);
+        }
+    }
+
+    /// Lowers the key of `i`, which must be in the heap
+    pub fn decrease_key(&mut self, i: usize, key: K) {
+        let p = self.positions[i].expect("Item not in the heap");
+        debug_assert!(self.keys[i].is_some_and(|k| key <= k), "Key increased");
+        self.keys[i] = Some(key);
+        self.sift_up(p);
+    }
+
+    /// Changes the key of `i`, which must be in the heap
+    pub fn update(&mut self, i: usize, key: K) {
+        let p = self.positions[i].expect("Item not in the heap");
+        self.keys[i] = Some(key);
+        let p = self.sift_up(p);
+        self.sift_down(p);
+    }
+
+    fn less(&self, a: usize, b: usize) -> bool {
+        self.keys[self.heap[a]] < self.keys[self.heap[b]]
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.positions[self.heap[a]] = Some(a);
+        self.positions[self.heap[b]] = Some(b);
+    }
+
+    fn remove_at(&mut self, p: usize) {
+        let last = self.heap.len() - 1;
+        self.swap(p, last);
+        let i = self.heap.pop().expect("Heap is not empty");
+        self.positions[i] = None;
+        if p < self.heap.len() {
+            let p = self.sift_up(p);
+            self.sift_down(p);
+        }
+    }
+
+    /// Moves the item at `p` up to its place, returning its new position
+    fn sift_up(&mut self, mut p: usize) -> usize {
+        while p > 0 && self.less(p, (p - 1) / 2) {
+            self.swap(p, (p - 1) / 2);
+            p = (p - 1) / 2;
+        }
+        p
+    }
+
+    fn sift_down(&mut self, mut p: usize) {
+        loop {
+            let mut min = p;
+            for c in [2 * p + 1, 2 * p + 2] {
+                if c < self.heap.len() && self.less(c, min) {
+                    min = c;
+                }
+            }
+            if min == p {
+                return;
+            }
+            self.swap(p, min);
+            p = min;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Items popped from `heap` until it is empty
+    fn drain(heap: &mut IndexedHeap<i32>) -> Vec<(usize, i32)> {
+        std::iter::from_fn(|| heap.pop()).collect()
+    }
+
+    #[test]
+    fn pops_in_key_order() {
+        let keys = [5, 3, 8, 1, 9, 2, 7];
+        let mut heap = IndexedHeap::new(keys.len());
+        for (i, k) in keys.iter().enumerate() {
+            heap.push(i, *k);
+        }
+        assert_eq!(heap.len(), keys.len());
+        assert_eq!(heap.peek(), Some((3, 1)));
+        let popped: Vec<i32> = drain(&mut heap).iter().map(|(_, k)| *k).collect();
+        assert_eq!(popped, vec![1, 2, 3, 5, 7, 8, 9]);
+        assert!(heap.is_empty());
+        assert_eq!(heap.pop(), None);
+    }
+
+    #[test]
+    fn key_updates_move_items() {
+        let mut heap = IndexedHeap::new(5);
+        for i in 0..5 {
+            heap.push(i, 10 * i as i32);
+        }
+        heap.decrease_key(4, -1);
+        heap.update(0, 25);
+        heap.push(2, 35);
+        heap.remove(1);
+        assert!(!heap.contains(1));
+        assert_eq!(heap.key(1), None);
+        assert_eq!(heap.key(0), Some(25));
+        assert_eq!(drain(&mut heap), vec![(4, -1), (0, 25), (3, 30), (2, 35)]);
+    }
+
+    #[test]
+    fn rebuild_replaces_the_items() {
+        let mut heap = IndexedHeap::new(6);
+        for i in 0..6 {
+            heap.push(i, i as i32);
+        }
+        heap.pop();
+        heap.rebuild([(5, 2), (0, 7), (3, -4), (1, 0)]);
+        assert_eq!(heap.len(), 4);
+        assert!(!heap.contains(2) && !heap.contains(4));
+        heap.decrease_key(0, 1);
+        assert_eq!(drain(&mut heap), vec![(3, -4), (1, 0), (0, 1), (5, 2)]);
+    }
+}
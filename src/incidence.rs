@@ -0,0 +1,266 @@
+//! Compressed sparse row incidence between sets and points
+//!
+//! A set whose smaller side, its points or the points it does not contain,
+//! takes fewer bits as 32 bit indices than the bit set of the set stores the
+//! indices of that side, back to back with those of the other such sets in a
+//! single array. The other side is enumerated by skipping the stored indices.
+//! Larger sets are read from their bit set, so the incidence never takes more
+//! memory than the sets themselves.
+
+use crate::bitset::WORD_BITS;
+use crate::ss::{Set, SetSystem};
+use rayon::prelude::*;
+use std::slice;
+
+/// Bits taken by a stored index
+const INDEX_BITS: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct Incidence<'a> {
+    n: usize,
+    sets: &'a [Set],
+    /// Stored indices of set `j` are `indices[offsets[j]..offsets[j + 1]]`
+    offsets: Vec<usize>,
+    indices: Vec<u32>,
+    storage: Vec<Storage>,
+}
+
+/// Side of a set stored in the incidence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Storage {
+    /// Points of the set
+    Members,
+    /// Points not in the set
+    NonMembers,
+    /// Nothing, the bit set of the set is read instead
+    Bits,
+}
+
+impl<'a> Incidence<'a> {
+    pub fn new(ss: &'a SetSystem) -> Incidence<'a> {
+        let n = ss.points.len();
+        assert!(n <= u32::MAX as usize, "Too many points for the incidence");
+        let sides: Vec<(Storage, Vec<u32>)> = ss
+            .sets
+            .par_iter()
+            .map(|s| {
+                let count = s.points.count_ones();
+                if INDEX_BITS * count.min(n - count) >= n {
+                    (Storage::Bits, Vec::new())
+                } else if 2 * count > n {
+                    (
+                        Storage::NonMembers,
+                        s.points.complement().ones().map(|x| x as u32).collect(),
+                    )
+                } else {
+                    (
+                        Storage::Members,
+                        s.points.ones().map(|x| x as u32).collect(),
+                    )
+                }
+            })
+            .collect();
+        let mut offsets = Vec::with_capacity(sides.len() + 1);
+        offsets.push(0);
+        for (_, v) in sides.iter() {
+            offsets.push(offsets.last().expect("Offsets start with 0") + v.len());
+        }
+        let mut indices = Vec::with_capacity(*offsets.last().expect("Offsets start with 0"));
+        let mut storage = Vec::with_capacity(sides.len());
+        for (c, v) in sides {
+            indices.extend_from_slice(&v);
+            storage.push(c);
+        }
+        Incidence {
+            n,
+            sets: &ss.sets,
+            offsets,
+            indices,
+            storage,
+        }
+    }
+
+    /// Number of sets
+    pub fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.storage.is_empty()
+    }
+
+    /// Number of points of set `j`
+    pub fn size(&self, j: usize) -> usize {
+        let stored = self.offsets[j + 1] - self.offsets[j];
+        match self.storage[j] {
+            Storage::Members => stored,
+            Storage::NonMembers => self.n - stored,
+            Storage::Bits => self.sets[j].points.count_ones(),
+        }
+    }
+
+    /// Points of set `j` if `inside`, the points it does not contain
+    /// otherwise, in increasing order
+    pub fn side(&self, j: usize, inside: bool) -> Side<'_> {
+        let stored = self.indices[self.offsets[j]..self.offsets[j + 1]].iter();
+        match self.storage[j] {
+            Storage::Bits => Side::Bits {
+                words: self.sets[j].points.words(),
+                flip: if inside { 0 } else { !0 },
+                n: self.n,
+                word: 0,
+                next: 0,
+            },
+            Storage::Members if inside => Side::Stored(stored),
+            Storage::NonMembers if !inside => Side::Stored(stored),
+            _ => Side::Skipping {
+                next: 0,
+                n: self.n,
+                skip: stored.peekable(),
+            },
+        }
+    }
+
+    /// Points of set `j`
+    pub fn members(&self, j: usize) -> Side<'_> {
+        self.side(j, true)
+    }
+
+    /// Points not in set `j`
+    pub fn non_members(&self, j: usize) -> Side<'_> {
+        self.side(j, false)
+    }
+}
+
+/// Iterator over one side of a set, see `Incidence::side`
+#[derive(Debug, Clone)]
+pub enum Side<'a> {
+    /// Side stored in the incidence
+    Stored(slice::Iter<'a, u32>),
+    /// Indices of `next..n` not in `skip`
+    Skipping {
+        next: usize,
+        n: usize,
+        skip: std::iter::Peekable<slice::Iter<'a, u32>>,
+    },
+    /// Set bits of `words` xor `flip` below `n`, `word` holding those of
+    /// word `next - 1` not returned yet
+    Bits {
+        words: &'a [u64],
+        flip: u64,
+        n: usize,
+        word: u64,
+        next: usize,
+    },
+}
+
+impl Iterator for Side<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        match self {
+            Side::Stored(it) => it.next().map(|x| *x as usize),
+            Side::Skipping { next, n, skip } => {
+                while *next < *n && skip.next_if(|x| **x as usize == *next).is_some() {
+                    *next += 1;
+                }
+                if *next < *n {
+                    *next += 1;
+                    Some(*next - 1)
+                } else {
+                    None
+                }
+            }
+            Side::Bits {
+                words,
+                flip,
+                n,
+                word,
+                next,
+            } => {
+                while *word == 0 {
+                    if *next * WORD_BITS >= *n {
+                        return None;
+                    }
+                    *word = words[*next] ^ *flip;
+                    //Flipped bits past n are not indices
+                    let end = *n - *next * WORD_BITS;
+                    if end < WORD_BITS {
+                        *word &= (1 << end) - 1;
+                    }
+                    *next += 1;
+                }
+                let b = word.trailing_zeros() as usize;
+                *word &= *word - 1;
+                Some((*next - 1) * WORD_BITS + b)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitset::BitSet;
+    use crate::ss::Point;
+
+    /// Set system over `n` points whose sets are given by their members
+    fn set_system(n: usize, sets: &[Vec<usize>]) -> SetSystem {
+        SetSystem {
+            points: (0..n)
+                .map(|index| Point {
+                    coordinates: vec![index as f32],
+                    index,
+                })
+                .collect(),
+            sets: sets
+                .iter()
+                .enumerate()
+                .map(|(index, members)| {
+                    let mut points = BitSet::new(n);
+                    for x in members {
+                        points.insert(*x);
+                    }
+                    Set { points, index }
+                })
+                .collect(),
+            ranges: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn sides_match_the_sets_whatever_the_storage() {
+        let n = 200;
+        let ss = set_system(
+            n,
+            &[
+                vec![],
+                vec![3],
+                vec![0, 5, 199],
+                (0..n).collect(),
+                (0..n).filter(|x| *x != 7).collect(),
+                (0..n).filter(|x| ![0, 64, 199].contains(x)).collect(),
+                (0..n).step_by(2).collect(),
+                (0..100).collect(),
+                (60..130).collect(),
+            ],
+        );
+        let incidence = Incidence::new(&ss);
+        assert_eq!(incidence.len(), ss.sets.len());
+        let storage = &incidence.storage;
+        assert_eq!(storage[1], Storage::Members);
+        assert_eq!(storage[4], Storage::NonMembers);
+        assert_eq!(storage[5], Storage::NonMembers);
+        assert_eq!(storage[6], Storage::Bits);
+        for (j, s) in ss.sets.iter().enumerate() {
+            let members: Vec<usize> = s.points.ones().collect();
+            let non_members: Vec<usize> = (0..n).filter(|x| !s.points[*x]).collect();
+            assert_eq!(incidence.members(j).collect::<Vec<usize>>(), members);
+            assert_eq!(
+                incidence.non_members(j).collect::<Vec<usize>>(),
+                non_members
+            );
+            assert_eq!(incidence.size(j), members.len());
+        }
+    }
+}
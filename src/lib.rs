@@ -0,0 +1,237 @@
+use rayon::prelude::*;
+use std::collections::HashSet;
+
+use bitset::BitSet;
+use ss::{Set, SetSystem};
+
+pub mod algos;
+pub mod binary;
+pub mod bitset;
+pub mod geometry;
+pub mod heap;
+pub mod incidence;
+pub mod partition;
+pub mod range;
+pub mod report;
+pub mod ss;
+
+/// Number of parts crossed by each set of `ss`
+pub fn intersections(parts: &[Set], ss: &[Set]) -> Vec<i32> {
+    ss.par_iter().map(|s| intersection(parts, s)).collect()
+}
+
+/// Number of parts crossed by each set of `ss`, followed by each of its
+/// ranges
+pub fn all_intersections(parts: &[Set], ss: &SetSystem) -> Vec<i32> {
+    let mut res = intersections(parts, &ss.sets);
+    res.extend(range::intersections(parts, &ss.ranges, &ss.points));
+    res
+}
+
+/// Positions in `parts` of the parts crossed by each set of `ss`, followed by
+/// each of its ranges
+pub fn all_crossed_parts(parts: &[Set], ss: &SetSystem) -> Vec<Vec<usize>> {
+    let mut res: Vec<Vec<usize>> = ss
+        .sets
+        .par_iter()
+        .map(|s| crossed_parts(parts, s))
+        .collect();
+    res.extend(range::crossed_parts(parts, &ss.ranges, &ss.points));
+    res
+}
+
+/// Number of parts crossed by the set `s`, i.e. containing points both
+/// inside and outside of `s`
+pub fn intersection(parts: &[Set], s: &Set) -> i32 {
+    parts.iter().filter(|p| p.points.crosses(&s.points)).count() as i32
+}
+
+/// Statistics of one part of a partition
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartStats {
+    /// Position of the part in the partition
+    pub part: usize,
+    pub size: usize,
+    /// Number of sets crossing the part
+    pub crossings: usize,
+    /// Largest distance between two points of the part
+    pub diameter: f32,
+    /// Smallest coordinates of the points of the part, on each axis (empty
+    /// for an empty part)
+    pub bbox_min: Vec<f32>,
+    /// Largest coordinates of the points of the part, on each axis
+    pub bbox_max: Vec<f32>,
+}
+
+/// Size, number of crossing sets and ranges, diameter and bounding box of
+/// every part
+///
+/// Points of the parts past those of the set system are ignored.
+pub fn part_stats(parts: &[Set], ss: &SetSystem) -> Vec<PartStats> {
+    let points = &ss.points;
+    let d = points.first().map_or(0, |p| p.coordinates.len());
+    let range_crossings = range::crossings(parts, &ss.ranges, points);
+    parts
+        .par_iter()
+        .zip(range_crossings.par_iter())
+        .enumerate()
+        .map(|(i, (p, ranges))| {
+            let members: Vec<&[f32]> = p
+                .points
+                .ones()
+                .filter_map(|x| points.get(x).map(|p| &p.coordinates[..]))
+                .collect();
+            let mut bbox_min = vec![f32::INFINITY; d];
+            let mut bbox_max = vec![f32::NEG_INFINITY; d];
+            let mut diameter: f32 = 0.;
+            for (k, x) in members.iter().enumerate() {
+                for c in 0..d {
+                    bbox_min[c] = bbox_min[c].min(x[c]);
+                    bbox_max[c] = bbox_max[c].max(x[c]);
+                }
+                for y in members[k + 1..].iter() {
+                    let dist: f32 = x.iter().zip(y.iter()).map(|(a, b)| (a - b) * (a - b)).sum();
+                    diameter = diameter.max(dist.sqrt());
+                }
+            }
+            if members.is_empty() {
+                bbox_min.clear();
+                bbox_max.clear();
+            }
+            PartStats {
+                part: i,
+                size: members.len(),
+                crossings: ss
+                    .sets
+                    .iter()
+                    .filter(|s| p.points.crosses(&s.points))
+                    .count()
+                    + ranges,
+                diameter,
+                bbox_min,
+                bbox_max,
+            }
+        })
+        .collect()
+}
+
+/// Positions in `parts` of the parts crossed by the set `s`
+pub fn crossed_parts(parts: &[Set], s: &Set) -> Vec<usize> {
+    parts
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.points.crosses(&s.points))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Number of sets crossing exactly `k` parts, for `k` from 0 to the maximum
+/// crossing number
+pub fn histogram(intersections: &[i32]) -> Vec<usize> {
+    let max = intersections.iter().copied().max().unwrap_or(0).max(0) as usize;
+    let mut res = vec![0; max + 1];
+    for x in intersections.iter() {
+        res[*x as usize] += 1;
+    }
+    res
+}
+
+/// Ratio of a crossing number to `t^(1 - 1/dim)`, the crossing number of an
+/// optimal partition into `t` parts for a set system of dual shatter
+/// dimension `dim`, up to a constant
+pub fn bound_ratio(crossings: i32, t: usize, dim: f64) -> f64 {
+    crossings as f64 / (t as f64).powf(1. - 1. / dim)
+}
+
+/// Largest dual shatter dimension `dual_shatter_dimension` can return
+const MAX_SHATTER_DIMENSION: usize = 10;
+
+/// Estimate of the dual shatter dimension of a set system
+///
+/// `k` of the sets, evenly spread over the sets followed by the ranges of
+/// `ss`, cut the points into cells. For sets like the halfspaces of
+/// dimension `dim`, there are `sum_{i < dim} C(k, i) + p C(k, dim)` cells,
+/// `p` in `(0, 1]` being the fraction of the `dim`-wise intersections of the
+/// sets that are among the points. The integer `dim` and `p` are fitted by
+/// least squares on the logarithms of the numbers of cells, for doubling
+/// values of `k` for which the cells are not yet bounded by the number of
+/// points. `None` if there are not enough sets or points to fit it.
+pub fn dual_shatter_dimension(ss: &SetSystem) -> Option<f64> {
+    let n = ss.points.len();
+    let m = ss.sets.len() + ss.ranges.len();
+    //Points of the j-th set, ranges being evaluated only when sampled
+    let set = |j: usize| match ss.sets.get(j) {
+        Some(s) => s.points.clone(),
+        None => ss.ranges[j - ss.sets.len()].to_bitset(&ss.points),
+    };
+    let mut samples = Vec::new();
+    let mut k = 2;
+    while k <= m {
+        let sampled: Vec<BitSet> = (0..k).map(|j| set(j * m / k)).collect();
+        let cells: HashSet<Vec<bool>> = (0..n)
+            .map(|x| sampled.iter().map(|s| s[x]).collect())
+            .collect();
+        if 4 * cells.len() > n {
+            break;
+        }
+        samples.push((k, (cells.len() as f64).ln()));
+        k *= 2;
+    }
+    if samples.len() < 2 {
+        return None;
+    }
+    let error = |dim: usize, ln_p: f64| -> f64 {
+        samples
+            .iter()
+            .map(|(k, cells)| (cells - cells_model(*k, dim, ln_p.exp()).ln()).powi(2))
+            .sum()
+    };
+    (1..=MAX_SHATTER_DIMENSION)
+        .map(|dim| {
+            //Ternary search of the best ln(p) in [-30, 0]
+            let (mut low, mut high) = (-30., 0.);
+            for _ in 0..100 {
+                let (a, b) = ((2. * low + high) / 3., (low + 2. * high) / 3.);
+                if error(dim, a) < error(dim, b) {
+                    high = b;
+                } else {
+                    low = a;
+                }
+            }
+            (error(dim, low), dim)
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, dim)| dim as f64)
+}
+
+/// `sum_{i < dim} C(k, i) + p C(k, dim)`, see `dual_shatter_dimension`
+fn cells_model(k: usize, dim: usize, p: f64) -> f64 {
+    let mut sum = 0.;
+    //C(k, i) for the current i
+    let mut binomial = 1.;
+    for i in 0..dim.min(k + 1) {
+        sum += binomial;
+        binomial *= (k - i) as f64 / (i + 1) as f64;
+    }
+    if dim <= k {
+        sum += p * binomial;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn dual_shatter_dimension_of_halfspaces() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for (d, n) in [(1, 1000), (2, 2000), (3, 5000)] {
+            let ss = SetSystem::rhs_ranges(n, 256, d, &mut rng);
+            let estimate = dual_shatter_dimension(&ss).expect("Enough points");
+            assert!((estimate - d as f64).abs() < 0.5, "d = {d}: {estimate}");
+        }
+    }
+}
@@ -1,18 +1,16 @@
-#[macro_use]
-extern crate rulinalg;
 use clap::{Args, Parser, Subcommand};
-use rayon::prelude::*;
-use std::cmp;
-use std::fmt;
-use std::fs::OpenOptions;
-use std::io::Write;
-use std::time::Duration;
+use num_integer::Roots;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
-use ss::Set;
-use ss::SetSystem;
-
-mod algos;
-mod ss;
+use partitions_rs::algos::{self, PartSizes, PartitionOptions};
+use partitions_rs::bitset::BitSet;
+use partitions_rs::partition::{self, Partition, PartitionError};
+use partitions_rs::report;
+use partitions_rs::ss::{BallCentre, BoxShape, Format, ParseError, Set, SetSystem};
+use partitions_rs::{
+    all_intersections, bound_ratio, dual_shatter_dimension, intersections, part_stats,
+};
 
 /// Compute a low-crossing partition of a set system
 #[derive(Parser)]
@@ -34,7 +32,7 @@ enum Commands {
 
 #[derive(Args)]
 struct GenerateArgs {
-    /// Type of set system to generate (grid or rhs)
+    /// Type of set system to generate (grid, rhs, halfspaces, box or ball)
     #[arg(short, long)]
     sstype: String,
 
@@ -49,23 +47,67 @@ struct GenerateArgs {
     /// Name of file to save the result
     #[arg(short, long)]
     output: Option<String>,
+
+    /// Format of the output file (binary for .bin files, dense otherwise)
+    #[arg(long, value_enum)]
+    format: Option<FileFormat>,
+
+    /// Seed of the random number generator (drawn at random if omitted)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Number of sets, n ln n by default (rhs, box, ball)
+    #[arg(long)]
+    sets: Option<i32>,
+
+    /// Volume of the boxes, uniform corners if omitted (box)
+    #[arg(long, value_parser = positive)]
+    box_volume: Option<f32>,
+
+    /// Fraction of the points in each ball, random for each ball if omitted (ball)
+    #[arg(long)]
+    fraction: Option<f32>,
+
+    /// Draw ball centres uniformly in the unit cube instead of on the points (ball)
+    #[arg(long)]
+    uniform_centres: bool,
+
+    /// Number of thresholds per axis for the grid set system (n^(1/d) - 1 by default)
+    #[arg(long)]
+    thresholds: Option<usize>,
+
+    /// Also add the two-sided slabs between thresholds to the grid set system
+    #[arg(long)]
+    two_sided: bool,
+
+    /// Store the halfspaces, boxes or balls as ranges instead of the points of every set (rhs, box, ball)
+    #[arg(long)]
+    implicit: bool,
 }
 
 #[derive(Args)]
 struct PartitionArgs {
-    /// Name of the algorithm used to create the partition
+    /// Name of the algorithm used to create the partition (min, atonce, potential, min-heap, min-par or potential-par)
     #[arg(short, long)]
-    algo: Algo,
+    algo: String,
 
     /// Number of parts in the partition
     #[arg(short, long)]
-    tpart: i32,
+    tpart: usize,
 
     /// File containing the set system to partition
     #[arg(short, long)]
     setsystem: String,
 
-    /// Number of rounds to simulate disctance function in the parallel algorithm
+    /// Use parts of floor(n/t) or ceil(n/t) points instead of putting the remainder in the last part
+    #[arg(long)]
+    balanced: bool,
+
+    /// Comma separated sizes of the t parts, summing to the number of points
+    #[arg(long, value_delimiter = ',', conflicts_with = "balanced")]
+    sizes: Option<Vec<usize>>,
+
+    /// Number of rounds to simulate distance function in the parallel algorithm (max(t, 30) by default)
     #[arg(short, long)]
     warmup: Option<i32>,
 
@@ -73,9 +115,21 @@ struct PartitionArgs {
     #[arg(short, long)]
     output: Option<String>,
 
-    /// Write the result stats to a file
+    /// Write the parts as a set system in this format instead of a partition file
+    #[arg(long, value_enum)]
+    format: Option<FileFormat>,
+
+    /// Append the result stats to a file, created with a header if missing (JSON lines for .jsonl, CSV otherwise)
     #[arg(short, long)]
     results: Option<String>,
+
+    /// Seed of the random number generator (drawn at random if omitted)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Dual shatter dimension of the set system for the crossing number bound (estimated if omitted)
+    #[arg(long)]
+    shatter_dim: Option<f64>,
 }
 
 #[derive(Args)]
@@ -87,21 +141,45 @@ struct IntersectionsArgs {
     /// Partition file
     #[arg(short, long)]
     partition: String,
+
+    /// Ignore the sets of the file and use every distinct halfspace over its points
+    #[arg(long)]
+    exact: bool,
+
+    /// Count crossings even if the parts do not form a partition of the points
+    #[arg(long)]
+    force: bool,
+
+    /// Write the crossing number and crossed parts of every set to a file (JSON for .json, CSV otherwise)
+    #[arg(long)]
+    report: Option<String>,
+
+    /// Write the histogram of crossing numbers to a file (JSON for .json, CSV otherwise)
+    #[arg(long)]
+    histogram: Option<String>,
+
+    /// Print how many sets cross each part, and write the size, crossings, diameter and bounding box of every part to a file if given
+    #[arg(long, num_args = 0..=1, default_missing_value = "")]
+    parts: Option<String>,
+
+    /// Dual shatter dimension of the set system for the crossing number bound (estimated if omitted)
+    #[arg(long)]
+    shatter_dim: Option<f64>,
 }
 
-#[derive(clap::ValueEnum, Clone, Debug)]
-enum Algo {
-    Min,
-    AO,
-    Potential,
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum FileFormat {
+    Dense,
+    Sparse,
+    Binary,
 }
 
-impl fmt::Display for Algo {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Algo::Min => write!(f, "min"),
-            Algo::AO => write!(f, "atonce"),
-            Algo::Potential => write!(f, "potential"),
+impl From<FileFormat> for Format {
+    fn from(f: FileFormat) -> Format {
+        match f {
+            FileFormat::Dense => Format::Dense,
+            FileFormat::Sparse => Format::Sparse,
+            FileFormat::Binary => Format::Binary,
         }
     }
 }
@@ -110,17 +188,74 @@ fn main() {
     let cli = Cli::parse();
     match &cli.command {
         Commands::Generate(args) => {
+            if args.implicit && !["rhs", "box", "ball"].contains(&args.sstype.as_str()) {
+                eprintln!("Set system {} cannot be stored as ranges", args.sstype);
+                std::process::exit(1);
+            }
+            if args.sstype == "rhs" && args.dimension == 0 {
+                eprintln!("The rhs set system needs a positive dimension");
+                std::process::exit(1);
+            }
             let d: usize = args.dimension;
+            let seed = seed(args.seed);
+            let mut rng = StdRng::seed_from_u64(seed);
             let n: i32;
             let ss: SetSystem;
             match args.sstype.as_str() {
                 "grid" => {
                     n = args.number.expect("n is required for the grid set system");
-                    ss = SetSystem::grid(n, d);
+                    let thresholds = args
+                        .thresholds
+                        .unwrap_or((n.nth_root(d as u32) as usize).saturating_sub(1));
+                    ss = SetSystem::slabs(n, d, thresholds, args.two_sided, &mut rng);
                 }
                 "rhs" => {
-                    n = args.number.expect("n is required for the grid set system");
-                    ss = SetSystem::rhs(n, ((n as f32) * (n as f32).ln()).floor() as i32, d);
+                    n = args.number.expect("n is required for the rhs set system");
+                    let m = args
+                        .sets
+                        .unwrap_or(((n as f32) * (n as f32).ln()).floor() as i32);
+                    ss = if args.implicit {
+                        SetSystem::rhs_ranges(n, m, d, &mut rng)
+                    } else {
+                        SetSystem::rhs(n, m, d, &mut rng)
+                    };
+                }
+                "halfspaces" => {
+                    n = args
+                        .number
+                        .expect("n is required for the halfspaces set system");
+                    ss = SetSystem::halfspaces(n, d, &mut rng);
+                }
+                "box" => {
+                    n = args.number.expect("n is required for the box set system");
+                    let m = args
+                        .sets
+                        .unwrap_or(((n as f32) * (n as f32).ln()).floor() as i32);
+                    let shape = match args.box_volume {
+                        None => BoxShape::Uniform,
+                        Some(v) => BoxShape::Volume(v),
+                    };
+                    ss = if args.implicit {
+                        SetSystem::box_ranges(n, m, d, shape, &mut rng)
+                    } else {
+                        SetSystem::boxes(n, m, d, shape, &mut rng)
+                    };
+                }
+                "ball" => {
+                    n = args.number.expect("n is required for the ball set system");
+                    let m = args
+                        .sets
+                        .unwrap_or(((n as f32) * (n as f32).ln()).floor() as i32);
+                    let centre = if args.uniform_centres {
+                        BallCentre::Uniform
+                    } else {
+                        BallCentre::Point
+                    };
+                    ss = if args.implicit {
+                        SetSystem::ball_ranges(n, m, d, args.fraction, centre, &mut rng)
+                    } else {
+                        SetSystem::balls(n, m, d, args.fraction, centre, &mut rng)
+                    };
                 }
                 _ => {
                     eprintln!("Invalid set system type: {}", args.sstype);
@@ -129,124 +264,296 @@ fn main() {
             }
             match &args.output {
                 None => (),
-                Some(x) => ss.to_file(x.as_str()),
+                Some(x) => ss.to_file_as(x, output_format(x, args.format)),
             }
         }
         Commands::Partition(args) => {
-            let ss: SetSystem = SetSystem::from_file(&args.setsystem);
+            let mut ss: SetSystem = load(&args.setsystem);
+            //The algorithms need the points of every set
+            ss.materialize();
             let t = args.tpart;
             let n = ss.points.len();
-            let res: SetSystem;
-            let time: Duration;
-            let mut warmup = (n as f32).sqrt() as i32;
-            match args.algo {
-                Algo::Min => {
-                    (res, time) = algos::part_min(&ss, t);
-                }
-                Algo::AO => match args.warmup {
-                    None => (res, time) = algos::part_at_once(&ss, t, cmp::max(t, 30)),
-                    Some(w) => {
-                        (res, time) = algos::part_at_once(&ss, t, w);
-                        warmup = w
-                    }
+            let partitioner = algos::partitioner(&args.algo).unwrap_or_else(|| {
+                let names: Vec<&str> = algos::partitioners().iter().map(|p| p.name()).collect();
+                eprintln!(
+                    "Unknown algorithm: {} (expected one of {})",
+                    args.algo,
+                    names.join(", ")
+                );
+                std::process::exit(1);
+            });
+            let options = PartitionOptions {
+                sizes: match (&args.sizes, args.balanced) {
+                    (Some(x), _) => PartSizes::Custom(x.clone()),
+                    (None, true) => PartSizes::Balanced,
+                    (None, false) => PartSizes::Remainder,
                 },
-                Algo::Potential => {
-                    (res, time) = algos::part_potential(&ss, t);
-                }
-            }
-            let intersections = intersections(&res.sets, &ss.sets);
+                warmup: args.warmup,
+            };
+            let seed = seed(args.seed);
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (mut partition, run) = partitioner
+                .partition(&ss, t, &options, &mut rng)
+                .unwrap_or_else(|e| {
+                    eprintln!("Invalid part sizes: {}", e);
+                    std::process::exit(1);
+                });
+            partition.metadata.seed = Some(seed);
+            let parts = partition.parts().expect("Algorithms return labels in 0..t");
+            println!("Building adjacency took: {:.3?}", run.adjacency_time);
+            println!("Elapsed: {:.3?}", run.partition_time);
             println!(
-                "Intersections : max -> {}, avg -> {}, min -> {}",
-                intersections
-                    .iter()
-                    .max()
-                    .expect("Fail to determine maximum"),
-                mean(&intersections),
-                intersections
-                    .iter()
-                    .min()
-                    .expect("Fail to determine intersection min")
+                "Weight doublings: {}, max set weight: 2^{}",
+                run.weight_doublings, run.max_set_weight
             );
-            match &args.results {
-                None => (),
-                Some(x) => {
-                    let mut file = OpenOptions::new().write(true).append(true).open(x).unwrap();
-
-                    if let Err(e) = writeln!(
-                        file,
-                        "{};{};{};{};{};{};{};{};{};{};{};{:.4};{};{}",
-                        args.algo,
-                        n,
-                        t,
-                        args.setsystem,
-                        ss.sets.len(),
-                        ss.points[0].coordinates.len(),
-                        0,
-                        intersections.iter().max().expect(""),
-                        mean(&intersections),
-                        intersections.iter().min().expect(""),
-                        warmup,
-                        time.as_secs_f64(),
-                        0,
-                        0
-                    ) {
-                        eprintln!("Couldn't write to file: {}", e);
-                    }
+            if let Some(x) = run.peak_memory {
+                println!("Peak memory: {:.1} MiB", x as f64 / (1024. * 1024.));
+            }
+            let intersections = intersections(&parts, &ss.sets);
+            let bounds = Bounds::new(&ss, t, args.shatter_dim);
+            let extremes = print_intersections(&intersections);
+            if let Some((max, _)) = extremes {
+                bounds.print(max);
+            }
+            if let (Some(x), None) = (&args.results, extremes) {
+                eprintln!("No sets, no results appended to file: {}", x);
+            }
+            if let (Some(x), Some((max, min))) = (&args.results, extremes) {
+                let run = report::Run {
+                    algorithm: partitioner.name().to_string(),
+                    setsystem: args.setsystem.clone(),
+                    n,
+                    m: ss.sets.len(),
+                    d: bounds.d,
+                    t,
+                    seed,
+                    warmup: run.warmup,
+                    max,
+                    avg: mean(&intersections),
+                    min,
+                    adjacency_time: Some(run.adjacency_time),
+                    partition_time: run.partition_time,
+                    peak_memory: run.peak_memory,
+                    weight_doublings: Some(run.weight_doublings),
+                    max_set_weight: Some(run.max_set_weight),
+                    ratio_d: bound_ratio(max, bounds.t, bounds.d as f64),
+                    ratio_sqrt: bound_ratio(max, bounds.t, 2.),
+                    shatter_dim: bounds.dim,
+                    ratio_shatter: bound_ratio(max, bounds.t, bounds.dim),
+                    revision: option_env!("GIT_REVISION").map(String::from),
+                };
+                if let Err(e) = report::append_run(x, &run) {
+                    eprintln!("Couldn't write to file: {}", e);
                 }
             }
             match &args.output {
                 None => (),
-                Some(x) => res.to_file(x.as_str()),
+                Some(x) => match args.format {
+                    None => partition.to_file(x),
+                    Some(f) => partition
+                        .to_set_system(&ss)
+                        .expect("Algorithms return labels in 0..t")
+                        .to_file_as(x, f.into()),
+                },
             }
         }
         Commands::Intersections(args) => {
-            let ss: SetSystem = SetSystem::from_file(&args.setsystem);
-            let part: SetSystem = SetSystem::from_file(&args.partition);
-            let intersections = intersections(&part.sets, &ss.sets);
-            println!(
-                "Intersections : max -> {}, avg -> {}, min -> {}",
-                intersections
-                    .iter()
-                    .max()
-                    .expect("Fail to determine maximum"),
-                mean(&intersections),
-                intersections
+            let mut ss: SetSystem = load(&args.setsystem);
+            //The partition was computed on the sets of the file
+            let parts = load_parts(&args.partition, &ss);
+            if args.exact {
+                ss.ranges.clear();
+                ss.sets = SetSystem::enumerate_halfspaces(&ss.points);
+                println!("Enumerated {} distinct halfspaces", ss.sets.len());
+            }
+            let validation = partition::validate(&parts, ss.points.len());
+            if !validation.is_partition() {
+                eprintln!("Invalid partition {}: {}", args.partition, validation);
+                if !args.force {
+                    std::process::exit(1);
+                }
+            } else if !validation.is_balanced() || !validation.empty_parts.is_empty() {
+                eprintln!("Warning: {}: {}", args.partition, validation);
+            }
+            //Forced parts only count the points of the set system
+            let parts: Vec<Set> = if validation.wrong_length.is_empty() {
+                parts
+            } else {
+                parts
+                    .into_iter()
+                    .map(|p| Set {
+                        points: BitSet::from_words(p.points.words().to_vec(), ss.points.len()),
+                        index: p.index,
+                    })
+                    .collect()
+            };
+            let intersections = all_intersections(&parts, &ss);
+            if let Some((max, _)) = print_intersections(&intersections) {
+                Bounds::new(&ss, parts.len(), args.shatter_dim).print(max);
+            }
+            if let Some(x) = &args.report {
+                match report::write_sets(x, &parts, &ss) {
+                    Ok(()) => println!("Set report written to file: {}", x),
+                    Err(e) => eprintln!("Couldn't write to file: {}", e),
+                }
+            }
+            if let Some(x) = &args.histogram {
+                match report::write_histogram(x, &intersections) {
+                    Ok(()) => println!("Histogram written to file: {}", x),
+                    Err(e) => eprintln!("Couldn't write to file: {}", e),
+                }
+            }
+            if let Some(x) = &args.parts {
+                let stats = part_stats(&parts, &ss);
+                let crossings: Vec<i32> = stats.iter().map(|p| p.crossings as i32).collect();
+                let worst = stats
                     .iter()
-                    .min()
-                    .expect("Fail to determine intersection min")
-            );
+                    .max_by_key(|p| p.crossings)
+                    .expect("Fail to determine maximum");
+                println!(
+                    "Sets crossing a part : max -> {} (part {}, {} points), avg -> {}, min -> {}",
+                    worst.crossings,
+                    worst.part,
+                    worst.size,
+                    mean(&crossings),
+                    crossings.iter().min().expect("Fail to determine minimum")
+                );
+                if !x.is_empty() {
+                    match report::write_parts(x, &stats) {
+                        Ok(()) => println!("Part report written to file: {}", x),
+                        Err(e) => eprintln!("Couldn't write to file: {}", e),
+                    }
+                }
+            }
         }
     }
 }
 
-fn mean(v: &Vec<i32>) -> f32 {
-    let mut sum = 0;
-    for x in v.iter() {
-        sum += x;
+/// Parameters of the `t^(1 - 1/dim)` bounds the crossing numbers are
+/// compared with
+struct Bounds {
+    t: usize,
+    /// Dimension of the points
+    d: usize,
+    /// Dual shatter dimension, declared, estimated, or that of the points
+    dim: f64,
+    source: DimensionSource,
+}
+
+/// Where the dual shatter dimension of `Bounds` comes from
+enum DimensionSource {
+    /// Given on the command line
+    Declared,
+    /// Estimated from the sets
+    Estimated,
+    /// Dimension of the points, used when there are too few sets to estimate
+    PointDimension,
+}
+
+impl Bounds {
+    fn new(ss: &SetSystem, t: usize, declared: Option<f64>) -> Bounds {
+        let d = ss.points.first().map_or(0, |p| p.coordinates.len());
+        let estimate = || dual_shatter_dimension(ss);
+        let (dim, source) = match declared {
+            Some(x) => (x, DimensionSource::Declared),
+            None => match estimate() {
+                Some(x) => (x, DimensionSource::Estimated),
+                None => (d as f64, DimensionSource::PointDimension),
+            },
+        };
+        Bounds { t, d, dim, source }
+    }
+
+    fn print(&self, max: i32) {
+        println!(
+            "Bounds : max / t^(1-1/{}) -> {:.4}, max / sqrt(t) -> {:.4}, max / t^(1-1/{:.2}) -> {:.4} ({})",
+            self.d,
+            bound_ratio(max, self.t, self.d as f64),
+            bound_ratio(max, self.t, 2.),
+            self.dim,
+            bound_ratio(max, self.t, self.dim),
+            match self.source {
+                DimensionSource::Declared => "declared dual shatter dimension",
+                DimensionSource::Estimated => "estimated dual shatter dimension",
+                DimensionSource::PointDimension => "dimension of the points as dual shatter dimension",
+            }
+        );
+    }
+}
+
+/// Parses a strictly positive number
+fn positive(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(x) if x > 0. => Ok(x),
+        Ok(_) => Err(format!("{} is not positive", s)),
+        Err(e) => Err(e.to_string()),
     }
-    sum as f32 / v.len() as f32
 }
 
-fn intersections(parts: &Vec<Set>, ss: &Vec<Set>) -> Vec<i32> {
-    let n = parts[0].points.len();
-    let inter = ss.par_iter().map(|s| intersection(parts, s, n)).collect();
-    inter
+/// Seed given on the command line, or a random one, printed so that the run
+/// can be reproduced
+fn seed(seed: Option<u64>) -> u64 {
+    let seed = seed.unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
+    seed
 }
 
-fn intersection(parts: &Vec<Set>, s: &Set, n: usize) -> i32 {
-    let mut res = 0;
-    for p in parts.iter() {
-        let mut start: usize = n + 1;
-        for i in 0..p.points.len() {
-            if p.points[i] {
-                if start == n + 1 {
-                    start = i;
-                } else if algos::intersects((start, i), s) {
-                    res += 1;
-                    break;
-                }
+fn output_format(filename: &str, format: Option<FileFormat>) -> Format {
+    format.map_or_else(|| Format::from_path(filename), Format::from)
+}
+
+fn load(filename: &str) -> SetSystem {
+    SetSystem::from_file(filename).unwrap_or_else(|e| {
+        eprintln!("Fail to read {}: {}", filename, e);
+        std::process::exit(1);
+    })
+}
+
+/// Parts stored in a partition file, or as the sets of a set system file
+fn load_parts(filename: &str, ss: &SetSystem) -> Vec<Set> {
+    match Partition::from_file(filename) {
+        Ok(p) => {
+            //Other problems are reported by the validation of the parts
+            if let Err(e @ PartitionError::Source { .. }) = p.check(ss) {
+                eprintln!("Warning: {}: {}", filename, e);
             }
+            p.parts().unwrap_or_else(|e| {
+                eprintln!("Invalid partition {}: {}", filename, e);
+                std::process::exit(1);
+            })
+        }
+        Err(ParseError::MissingSection(partition::HEADER)) => load(filename).sets,
+        Err(e) => {
+            eprintln!("Fail to read {}: {}", filename, e);
+            std::process::exit(1);
         }
     }
-    res
+}
+
+/// Prints the largest, average and smallest crossing numbers, and returns
+/// the largest and smallest ones, `None` if there are no sets
+fn print_intersections(intersections: &[i32]) -> Option<(i32, i32)> {
+    match (intersections.iter().max(), intersections.iter().min()) {
+        (Some(max), Some(min)) => {
+            println!(
+                "Intersections : max -> {}, avg -> {}, min -> {}",
+                max,
+                mean(intersections),
+                min
+            );
+            Some((*max, *min))
+        }
+        _ => {
+            println!("Intersections : no sets");
+            None
+        }
+    }
+}
+
+fn mean(v: &[i32]) -> f32 {
+    let mut sum = 0;
+    for x in v.iter() {
+        sum += x;
+    }
+    sum as f32 / v.len() as f32
 }
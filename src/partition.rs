@@ -0,0 +1,393 @@
+//! Partitions of the points of a set system
+//!
+//! A partition file starts with a `partition` line, followed by optional
+//! `key=value` metadata lines (`t`, `algorithm`, `seed` and `source`, the
+//! hexadecimal fingerprint of the partitioned set system), and by a `labels`
+//! line after which every line holds the part of one point, in point order.
+
+use crate::bitset::BitSet;
+use crate::ss::{ParseError, Set, SetSystem};
+use std::{error, fmt, fs, io::BufWriter, io::Write};
+
+pub const HEADER: &str = "partition";
+
+/// Information recorded alongside a partition
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub algorithm: Option<String>,
+    pub seed: Option<u64>,
+    /// `SetSystem::fingerprint` of the partitioned set system
+    pub source: Option<u64>,
+}
+
+/// Partition of the points of a set system into `t` parts, stored as the
+/// part of every point
+#[derive(Debug, Clone)]
+pub struct Partition {
+    pub labels: Vec<usize>,
+    pub t: usize,
+    pub metadata: Metadata,
+}
+
+/// Error returned when parts do not form a partition of a set system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionError {
+    /// A point is in two parts
+    Overlap { point: usize, parts: (usize, usize) },
+    /// A point is in no part
+    Uncovered { point: usize },
+    /// The partition does not have one label per point of the set system
+    PointCount { expected: usize, found: usize },
+    /// The partition does not have the expected number of parts
+    PartCount { expected: usize, found: usize },
+    /// A part has no point
+    EmptyPart { part: usize },
+    /// A point has a label out of `0..t`
+    Label { point: usize, label: usize },
+    /// The partition was computed on another set system
+    Source { expected: u64, found: u64 },
+}
+
+impl fmt::Display for PartitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PartitionError::Overlap { point, parts } => write!(
+                f,
+                "point {} is in both parts {} and {}",
+                point, parts.0, parts.1
+            ),
+            PartitionError::Uncovered { point } => write!(f, "point {} is in no part", point),
+            PartitionError::PointCount { expected, found } => write!(
+                f,
+                "partition has {} points, the set system has {}",
+                found, expected
+            ),
+            PartitionError::PartCount { expected, found } => {
+                write!(f, "partition has {} parts, expected {}", found, expected)
+            }
+            PartitionError::EmptyPart { part } => write!(f, "part {} is empty", part),
+            PartitionError::Label { point, label } => {
+                write!(f, "point {} has label {} out of range", point, label)
+            }
+            PartitionError::Source { expected, found } => write!(
+                f,
+                "partition was computed on set system {:016x}, not {:016x}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl error::Error for PartitionError {}
+
+/// Problems found by `validate` in a collection of parts
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validation {
+    /// Parts that do not have one entry per point
+    pub wrong_length: Vec<usize>,
+    /// Points in more than one part
+    pub overlaps: Vec<usize>,
+    /// Points in no part
+    pub uncovered: Vec<usize>,
+    /// Positions of the empty parts
+    pub empty_parts: Vec<usize>,
+    /// Number of points in every part
+    pub sizes: Vec<usize>,
+}
+
+impl Validation {
+    /// Whether every point is in exactly one part
+    pub fn is_partition(&self) -> bool {
+        self.wrong_length.is_empty() && self.overlaps.is_empty() && self.uncovered.is_empty()
+    }
+
+    /// Difference between the sizes of the largest and smallest parts
+    pub fn imbalance(&self) -> usize {
+        match (self.sizes.iter().max(), self.sizes.iter().min()) {
+            (Some(max), Some(min)) => max - min,
+            _ => 0,
+        }
+    }
+
+    /// Whether all parts have `floor(n/t)` or `ceil(n/t)` points
+    pub fn is_balanced(&self) -> bool {
+        self.imbalance() <= 1
+    }
+}
+
+impl fmt::Display for Validation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut problems = Vec::new();
+        let mut list = |what: &str, v: &[usize]| {
+            if !v.is_empty() {
+                let first: Vec<String> = v.iter().take(5).map(|x| x.to_string()).collect();
+                let more = if v.len() > 5 { ", ..." } else { "" };
+                problems.push(format!(
+                    "{} {} ({}{})",
+                    v.len(),
+                    what,
+                    first.join(", "),
+                    more
+                ));
+            }
+        };
+        list("parts with a wrong number of points", &self.wrong_length);
+        list("points in several parts", &self.overlaps);
+        list("points in no part", &self.uncovered);
+        list("empty parts", &self.empty_parts);
+        if !self.is_balanced() {
+            problems.push(format!(
+                "part sizes range from {} to {}",
+                self.sizes.iter().min().unwrap_or(&0),
+                self.sizes.iter().max().unwrap_or(&0)
+            ));
+        }
+        if problems.is_empty() {
+            write!(f, "valid balanced partition")
+        } else {
+            write!(f, "{}", problems.join(", "))
+        }
+    }
+}
+
+/// Checks that `parts` form a balanced partition of `n` points
+pub fn validate(parts: &[Set], n: usize) -> Validation {
+    let mut res = Validation::default();
+    let mut seen = BitSet::new(n);
+    let mut overlaps = BitSet::new(n);
+    for (i, p) in parts.iter().enumerate() {
+        if p.points.len() != n {
+            res.wrong_length.push(i);
+        }
+        let size = p.points.count_ones();
+        if size == 0 {
+            res.empty_parts.push(i);
+        }
+        res.sizes.push(size);
+        for x in p.points.ones().filter(|x| *x < n) {
+            if seen[x] {
+                overlaps.insert(x);
+            }
+            seen.insert(x);
+        }
+    }
+    res.overlaps = overlaps.ones().collect();
+    res.uncovered = seen.complement().ones().collect();
+    res
+}
+
+impl Partition {
+    /// Partition whose `i`-th part is `parts[i]`, all parts being over the
+    /// same points
+    pub fn from_parts(parts: &[Set]) -> Result<Partition, PartitionError> {
+        let n = parts.first().map_or(0, |p| p.points.len());
+        let mut labels: Vec<Option<usize>> = vec![None; n];
+        for (i, p) in parts.iter().enumerate() {
+            if p.points.len() != n {
+                return Err(PartitionError::PointCount {
+                    expected: n,
+                    found: p.points.len(),
+                });
+            }
+            for x in p.points.ones() {
+                if let Some(j) = labels[x] {
+                    return Err(PartitionError::Overlap {
+                        point: x,
+                        parts: (j, i),
+                    });
+                }
+                labels[x] = Some(i);
+            }
+        }
+        let labels = labels
+            .into_iter()
+            .enumerate()
+            .map(|(point, l)| l.ok_or(PartitionError::Uncovered { point }))
+            .collect::<Result<Vec<usize>, PartitionError>>()?;
+        Ok(Partition {
+            labels,
+            t: parts.len(),
+            metadata: Metadata::default(),
+        })
+    }
+
+    /// Parts of the partition, indexed from 1 as returned by the algorithms
+    ///
+    /// Fails if a label is out of `0..t`.
+    pub fn parts(&self) -> Result<Vec<Set>, PartitionError> {
+        let mut parts: Vec<BitSet> = vec![BitSet::new(self.labels.len()); self.t];
+        for (x, l) in self.labels.iter().enumerate() {
+            match parts.get_mut(*l) {
+                Some(p) => p.insert(x),
+                None => {
+                    return Err(PartitionError::Label {
+                        point: x,
+                        label: *l,
+                    })
+                }
+            }
+        }
+        Ok(parts
+            .into_iter()
+            .enumerate()
+            .map(|(i, points)| Set {
+                points,
+                index: i + 1,
+            })
+            .collect())
+    }
+
+    /// Partition in the set system layout, the parts being the sets
+    pub fn to_set_system(&self, ss: &SetSystem) -> Result<SetSystem, PartitionError> {
+        Ok(SetSystem {
+            points: ss.points.clone(),
+            sets: self.parts()?,
+            ranges: Vec::new(),
+        })
+    }
+
+    /// Checks the parts of the partition against the points of `ss`, see
+    /// `validate`
+    pub fn validate(&self, ss: &SetSystem) -> Result<Validation, PartitionError> {
+        Ok(validate(&self.parts()?, ss.points.len()))
+    }
+
+    /// Checks that the partition is a partition of the points of `ss`
+    pub fn check(&self, ss: &SetSystem) -> Result<(), PartitionError> {
+        if self.labels.len() != ss.points.len() {
+            return Err(PartitionError::PointCount {
+                expected: ss.points.len(),
+                found: self.labels.len(),
+            });
+        }
+        if let Some((point, label)) = self.labels.iter().enumerate().find(|(_, l)| **l >= self.t) {
+            return Err(PartitionError::Label {
+                point,
+                label: *label,
+            });
+        }
+        match self.metadata.source {
+            Some(found) if found != ss.fingerprint() => Err(PartitionError::Source {
+                expected: ss.fingerprint(),
+                found,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn from_file(filename: &str) -> Result<Partition, ParseError> {
+        let bytes = fs::read(filename)?;
+        let content = String::from_utf8_lossy(&bytes);
+        if content.split('\n').next().map(|l| l.trim_end_matches('\r')) != Some(HEADER) {
+            return Err(ParseError::MissingSection(HEADER));
+        }
+        let mut t = None;
+        let mut metadata = Metadata::default();
+        let mut labels = Vec::new();
+        let mut in_labels = false;
+        for (line, l) in content.split('\n').enumerate().skip(1) {
+            let line = line + 1;
+            let l = l.trim_end_matches('\r');
+            if l.is_empty() {
+                continue;
+            }
+            if in_labels {
+                match l.parse::<usize>() {
+                    Ok(x) => labels.push(x),
+                    Err(_) => return Err(ParseError::invalid_token(line, 1, l)),
+                }
+            } else if l == "labels" {
+                in_labels = true;
+            } else {
+                let (key, value) = l
+                    .split_once('=')
+                    .ok_or_else(|| ParseError::invalid_token(line, 1, l))?;
+                let column = key.len() + 2;
+                let invalid = || ParseError::invalid_token(line, column, value);
+                match key {
+                    "t" => t = Some(value.parse::<usize>().map_err(|_| invalid())?),
+                    "algorithm" => metadata.algorithm = Some(value.to_string()),
+                    "seed" => metadata.seed = Some(value.parse::<u64>().map_err(|_| invalid())?),
+                    "source" => {
+                        metadata.source =
+                            Some(u64::from_str_radix(value, 16).map_err(|_| invalid())?)
+                    }
+                    _ => return Err(ParseError::invalid_token(line, 1, key)),
+                }
+            }
+        }
+        if !in_labels {
+            return Err(ParseError::MissingSection("labels"));
+        }
+        let t = t.unwrap_or_else(|| labels.iter().max().map_or(0, |x| x + 1));
+        Ok(Partition {
+            labels,
+            t,
+            metadata,
+        })
+    }
+
+    pub fn to_file(&self, filename: &str) {
+        let mut file = BufWriter::new(fs::File::create(filename).expect("Fail to create file"));
+        writeln!(file, "{}", HEADER).expect("Fail to write");
+        writeln!(file, "t={}", self.t).expect("Fail to write");
+        if let Some(a) = &self.metadata.algorithm {
+            writeln!(file, "algorithm={}", a).expect("Fail to write");
+        }
+        if let Some(s) = self.metadata.seed {
+            writeln!(file, "seed={}", s).expect("Fail to write");
+        }
+        if let Some(s) = self.metadata.source {
+            writeln!(file, "source={:016x}", s).expect("Fail to write");
+        }
+        writeln!(file, "labels").expect("Fail to write");
+        for l in self.labels.iter() {
+            writeln!(file, "{}", l).expect("Fail to write");
+        }
+        file.flush().expect("Fail to write");
+        println!("Partition written to file: {}", filename);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(n: usize, members: &[usize]) -> Set {
+        let mut points = BitSet::new(n);
+        for x in members {
+            points.insert(*x);
+        }
+        Set { points, index: 0 }
+    }
+
+    #[test]
+    fn from_parts_rejects_parts_over_other_points() {
+        let parts = [part(4, &[0, 1]), part(5, &[2, 3, 4])];
+        assert_eq!(
+            Partition::from_parts(&parts).err(),
+            Some(PartitionError::PointCount {
+                expected: 4,
+                found: 5
+            })
+        );
+        let parts = [part(4, &[0, 1]), part(4, &[2, 3])];
+        assert_eq!(
+            Partition::from_parts(&parts).map(|p| p.labels).ok(),
+            Some(vec![0, 0, 1, 1])
+        );
+    }
+
+    #[test]
+    fn parts_reject_labels_out_of_range() {
+        let partition = Partition {
+            labels: vec![0, 1, 2, 1],
+            t: 2,
+            metadata: Metadata::default(),
+        };
+        assert_eq!(
+            partition.parts().err(),
+            Some(PartitionError::Label { point: 2, label: 2 })
+        );
+    }
+}
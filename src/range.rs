@@ -0,0 +1,440 @@
+//! Sets given by a geometric predicate on the coordinates of the points
+//!
+//! In the text formats, a `ranges` line after the sets is followed by one
+//! range per line: `halfspace` followed by the normal `a` and the offset `b`
+//! of `{x : a . x > b}`, `ball` followed by the centre `c` and the squared
+//! radius `r` of `{x : |x - c|^2 <= r}`, or `box` followed by the smallest
+//! and the largest corners of the box, all comma separated. A leading `-`
+//! stands for the complement of the range.
+
+use crate::bitset::BitSet;
+use crate::geometry::Hyperplane;
+use crate::ss::{cells, ParseError, Point, Set};
+use rayon::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+
+/// Relative margin under which `Range::relation` does not decide, so that
+/// its answer never differs from `Range::contains` because of rounding
+const MARGIN: f64 = 1e-5;
+
+#[derive(Debug, Clone)]
+pub enum Range {
+    /// Points strictly above the hyperplane
+    Halfspace(Hyperplane),
+    /// Points at squared distance at most `squared_radius` from `centre`
+    Ball {
+        centre: Vec<f32>,
+        squared_radius: f32,
+    },
+    /// Points between `low` and `high` on every axis, bounds included
+    Box { low: Vec<f32>, high: Vec<f32> },
+    /// Points not in the range
+    Complement(Box<Range>),
+}
+
+impl Range {
+    pub fn contains(&self, x: &[f32]) -> bool {
+        match self {
+            Range::Halfspace(h) => h.eval(x) > 0.,
+            Range::Ball {
+                centre,
+                squared_radius,
+            } => squared_distance(centre, x) <= *squared_radius,
+            Range::Box { low, high } => (0..low.len()).all(|k| low[k] <= x[k] && x[k] <= high[k]),
+            Range::Complement(r) => !r.contains(x),
+        }
+    }
+
+    /// Points of `points` in the range
+    pub fn to_bitset(&self, points: &[Point]) -> BitSet {
+        points
+            .iter()
+            .map(|p| self.contains(&p.coordinates))
+            .collect()
+    }
+
+    /// `Some(true)` if the box between `low` and `high` is inside the range,
+    /// `Some(false)` if it is outside, and `None` if it may be crossed
+    pub fn relation(&self, low: &[f32], high: &[f32]) -> Option<bool> {
+        match self {
+            Range::Halfspace(h) => {
+                //Extreme values of the normal over the box
+                let (mut min, mut max) = (-h.offset, -h.offset);
+                for (a, (l, u)) in h.normal.iter().zip(low.iter().zip(high.iter())) {
+                    let (l, u) = (a * *l as f64, a * *u as f64);
+                    min += l.min(u);
+                    max += l.max(u);
+                }
+                let margin = MARGIN * (1. + h.offset.abs());
+                if min > margin {
+                    Some(true)
+                } else if max < -margin {
+                    Some(false)
+                } else {
+                    None
+                }
+            }
+            Range::Ball {
+                centre,
+                squared_radius,
+            } => {
+                let (mut near, mut far) = (0., 0.);
+                for (c, (l, u)) in centre.iter().zip(low.iter().zip(high.iter())) {
+                    let (c, l, u) = (*c as f64, *l as f64, *u as f64);
+                    let gap = (l - c).max(c - u).max(0.);
+                    near += gap * gap;
+                    far += (c - l).abs().max((u - c).abs()).powi(2);
+                }
+                let r = *squared_radius as f64;
+                let margin = MARGIN * (1. + r);
+                if far < r - margin {
+                    Some(true)
+                } else if near > r + margin {
+                    Some(false)
+                } else {
+                    None
+                }
+            }
+            Range::Box { low: bl, high: bh } => {
+                let k = bl.len();
+                if (0..k).all(|i| bl[i] <= low[i] && high[i] <= bh[i]) {
+                    Some(true)
+                } else if (0..k).any(|i| high[i] < bl[i] || bh[i] < low[i]) {
+                    Some(false)
+                } else {
+                    None
+                }
+            }
+            Range::Complement(r) => r.relation(low, high).map(|inside| !inside),
+        }
+    }
+
+    /// Whether the points of `members`, whose bounding box is `low`, `high`,
+    /// are on both sides of the range
+    pub fn crosses(&self, points: &[Point], members: &[usize], low: &[f32], high: &[f32]) -> bool {
+        if members.is_empty() || self.relation(low, high).is_some() {
+            return false;
+        }
+        let mut inside = false;
+        let mut outside = false;
+        for x in members.iter() {
+            if self.contains(&points[*x].coordinates) {
+                inside = true;
+            } else {
+                outside = true;
+            }
+            if inside && outside {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Range described by a line of the `ranges` section of a file of points
+    /// of dimension `d`
+    pub fn parse(line: usize, l: &str, d: usize) -> Result<Range, ParseError> {
+        let complements = l.len() - l.trim_start_matches('-').len();
+        let cells = cells(&l[complements..]);
+        let (column, kind) = *cells
+            .first()
+            .ok_or_else(|| ParseError::invalid_token(line, 1, l))?;
+        let values = &cells[1..];
+        let expected = match kind {
+            "halfspace" | "ball" => d + 1,
+            "box" => 2 * d,
+            _ => return Err(ParseError::invalid_token(line, complements + column, kind)),
+        };
+        if values.len() != expected {
+            return Err(ParseError::RangeLength {
+                line,
+                expected,
+                found: values.len(),
+            });
+        }
+        let mut res = match kind {
+            "halfspace" => {
+                let v: Vec<f64> = parse_all(line, complements, values)?;
+                Range::Halfspace(Hyperplane {
+                    normal: v[..d].to_vec(),
+                    offset: v[d],
+                })
+            }
+            "ball" => {
+                let v: Vec<f32> = parse_all(line, complements, values)?;
+                Range::Ball {
+                    centre: v[..d].to_vec(),
+                    squared_radius: v[d],
+                }
+            }
+            _ => {
+                let v: Vec<f32> = parse_all(line, complements, values)?;
+                Range::Box {
+                    low: v[..d].to_vec(),
+                    high: v[d..].to_vec(),
+                }
+            }
+        };
+        for _ in 0..complements {
+            res = Range::Complement(Box::new(res));
+        }
+        Ok(res)
+    }
+}
+
+/// Line of the `ranges` section of a file describing the range
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |v: &[String]| v.join(",");
+        let floats = |v: &[f32]| v.iter().map(|x| x.to_string()).collect::<Vec<String>>();
+        match self {
+            Range::Halfspace(h) => {
+                let mut v: Vec<String> = h.normal.iter().map(|x| x.to_string()).collect();
+                v.push(h.offset.to_string());
+                write!(f, "halfspace,{}", join(&v))
+            }
+            Range::Ball {
+                centre,
+                squared_radius,
+            } => {
+                let mut v = floats(centre);
+                v.push(squared_radius.to_string());
+                write!(f, "ball,{}", join(&v))
+            }
+            Range::Box { low, high } => {
+                let mut v = floats(low);
+                v.extend(floats(high));
+                write!(f, "box,{}", join(&v))
+            }
+            Range::Complement(r) => write!(f, "-{}", r),
+        }
+    }
+}
+
+/// Values of the `(column, token)` cells of a line, whose first `shift`
+/// characters were stripped
+fn parse_all<T: FromStr>(
+    line: usize,
+    shift: usize,
+    cells: &[(usize, &str)],
+) -> Result<Vec<T>, ParseError> {
+    cells
+        .iter()
+        .map(|(c, x)| {
+            x.parse::<T>()
+                .map_err(|_| ParseError::invalid_token(line, shift + c, x))
+        })
+        .collect()
+}
+
+fn squared_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+/// Points of a part among those of the set system, and their bounding box
+type PartBox = (Vec<usize>, Vec<f32>, Vec<f32>);
+
+/// Bounding boxes of the parts, for `Range::crosses`
+fn part_boxes(parts: &[Set], points: &[Point]) -> Vec<PartBox> {
+    let d = points.first().map_or(0, |p| p.coordinates.len());
+    parts
+        .par_iter()
+        .map(|p| {
+            let members: Vec<usize> = p.points.ones().filter(|x| *x < points.len()).collect();
+            let mut low = vec![f32::INFINITY; d];
+            let mut high = vec![f32::NEG_INFINITY; d];
+            for x in members.iter() {
+                for (k, c) in points[*x].coordinates.iter().enumerate() {
+                    low[k] = low[k].min(*c);
+                    high[k] = high[k].max(*c);
+                }
+            }
+            (members, low, high)
+        })
+        .collect()
+}
+
+/// Number of parts crossed by each range, computed from the coordinates of
+/// the points without storing the ranges as sets
+///
+/// Points of the parts past those of `points` are ignored.
+pub fn intersections(parts: &[Set], ranges: &[Range], points: &[Point]) -> Vec<i32> {
+    if ranges.is_empty() {
+        return Vec::new();
+    }
+    let boxes = part_boxes(parts, points);
+    ranges
+        .par_iter()
+        .map(|r| {
+            boxes
+                .iter()
+                .filter(|(members, low, high)| r.crosses(points, members, low, high))
+                .count() as i32
+        })
+        .collect()
+}
+
+/// Positions in `parts` of the parts crossed by each range, see
+/// `intersections`
+pub fn crossed_parts(parts: &[Set], ranges: &[Range], points: &[Point]) -> Vec<Vec<usize>> {
+    if ranges.is_empty() {
+        return Vec::new();
+    }
+    let boxes = part_boxes(parts, points);
+    ranges
+        .par_iter()
+        .map(|r| {
+            boxes
+                .iter()
+                .enumerate()
+                .filter(|(_, (members, low, high))| r.crosses(points, members, low, high))
+                .map(|(i, _)| i)
+                .collect()
+        })
+        .collect()
+}
+
+/// Number of ranges crossing each part, see `intersections`
+pub fn crossings(parts: &[Set], ranges: &[Range], points: &[Point]) -> Vec<usize> {
+    if ranges.is_empty() {
+        return vec![0; parts.len()];
+    }
+    part_boxes(parts, points)
+        .par_iter()
+        .map(|(members, low, high)| {
+            ranges
+                .iter()
+                .filter(|r| r.crosses(points, members, low, high))
+                .count()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn coordinates(d: usize, rng: &mut StdRng) -> Vec<f32> {
+        (0..d).map(|_| rng.gen()).collect()
+    }
+
+    fn random_range(d: usize, rng: &mut StdRng) -> Range {
+        let range = match rng.gen_range(0..3) {
+            0 => Range::Halfspace(Hyperplane {
+                normal: (0..d).map(|_| rng.gen_range(-1.0..1.0)).collect(),
+                offset: rng.gen_range(-1.0..1.0),
+            }),
+            1 => Range::Ball {
+                centre: coordinates(d, rng),
+                squared_radius: rng.gen_range(0.0..0.5),
+            },
+            _ => {
+                let (a, b) = (coordinates(d, rng), coordinates(d, rng));
+                Range::Box {
+                    low: a.iter().zip(b.iter()).map(|(x, y)| x.min(*y)).collect(),
+                    high: a.iter().zip(b.iter()).map(|(x, y)| x.max(*y)).collect(),
+                }
+            }
+        };
+        if rng.gen() {
+            Range::Complement(Box::new(range))
+        } else {
+            range
+        }
+    }
+
+    #[test]
+    fn intersections_ignore_points_past_the_set_system() {
+        let points: Vec<Point> = (0..2)
+            .map(|index| Point {
+                coordinates: vec![index as f32],
+                index,
+            })
+            .collect();
+        let mut part = BitSet::new(3);
+        for x in 0..3 {
+            part.insert(x);
+        }
+        let parts = [Set {
+            points: part,
+            index: 1,
+        }];
+        assert!(intersections(&parts, &[], &points).is_empty());
+        let ball = Range::Ball {
+            centre: vec![0.],
+            squared_radius: 0.25,
+        };
+        assert_eq!(intersections(&parts, &[ball], &points), vec![1]);
+    }
+
+    #[test]
+    fn crossed_parts_match_the_materialized_ranges() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let (n, d, t) = (300, 2, 10);
+        let points: Vec<Point> = (0..n)
+            .map(|index| Point {
+                coordinates: coordinates(d, &mut rng),
+                index,
+            })
+            .collect();
+        let mut parts: Vec<Set> = (0..t)
+            .map(|index| Set {
+                points: BitSet::new(n),
+                index,
+            })
+            .collect();
+        //Parts of points close to each other, so that some are not crossed
+        for p in points.iter() {
+            let i = (p.coordinates[0] * t as f32) as usize;
+            parts[i.min(t - 1)].points.insert(p.index);
+        }
+        let ranges: Vec<Range> = (0..50).map(|_| random_range(d, &mut rng)).collect();
+        let sets: Vec<BitSet> = ranges.iter().map(|r| r.to_bitset(&points)).collect();
+        let crossed = crossed_parts(&parts, &ranges, &points);
+        for (c, s) in crossed.iter().zip(sets.iter()) {
+            let expected: Vec<usize> = (0..t).filter(|i| parts[*i].points.crosses(s)).collect();
+            assert_eq!(c, &expected);
+        }
+        let counts: Vec<i32> = crossed.iter().map(|c| c.len() as i32).collect();
+        assert_eq!(intersections(&parts, &ranges, &points), counts);
+        let expected: Vec<usize> = parts
+            .iter()
+            .map(|p| sets.iter().filter(|s| p.points.crosses(s)).count())
+            .collect();
+        assert_eq!(crossings(&parts, &ranges, &points), expected);
+    }
+
+    #[test]
+    fn relation_agrees_with_contains() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let d = 3;
+        let mut decided = 0;
+        for _ in 0..2000 {
+            let range = random_range(d, &mut rng);
+            //Small boxes, so that many are on one side of the range
+            let low: Vec<f32> = (0..d).map(|_| rng.gen_range(0.0..0.8)).collect();
+            let high: Vec<f32> = low.iter().map(|x| x + rng.gen_range(0.0..0.2)).collect();
+            let Some(inside) = range.relation(&low, &high) else {
+                continue;
+            };
+            decided += 1;
+            //Corners and random points of the box
+            let corners = (0..1 << d).map(|c: usize| {
+                (0..d)
+                    .map(|k| if c >> k & 1 == 1 { high[k] } else { low[k] })
+                    .collect::<Vec<f32>>()
+            });
+            let interior = (0..20).map(|_| {
+                (0..d)
+                    .map(|k| rng.gen_range(low[k]..=high[k]))
+                    .collect::<Vec<f32>>()
+            });
+            for x in corners.chain(interior).collect::<Vec<_>>() {
+                assert_eq!(range.contains(&x), inside, "{} at {:?}", range, x);
+            }
+        }
+        assert!(decided > 1000, "only {} boxes decided", decided);
+    }
+}
@@ -0,0 +1,293 @@
+//! Detailed crossing reports written by the `intersections` command, and
+//! results of the `partition` runs
+//!
+//! Reports are written as JSON for `.json` files and as CSV otherwise.
+//! Results are appended as JSON lines for `.jsonl` files and as CSV rows
+//! otherwise.
+
+use crate::ss::{Set, SetSystem};
+use crate::{all_crossed_parts, histogram, PartStats};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::time::Duration;
+use std::{fs, io};
+
+fn is_json(filename: &str) -> bool {
+    filename.ends_with(".json")
+}
+
+fn join(v: &[usize], sep: &str) -> String {
+    v.iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+/// Writes, for every set and then every range, its crossing number and the
+/// positions of the parts it crosses
+///
+/// Ranges are numbered after the sets, as by `SetSystem::materialize`.
+pub fn write_sets(filename: &str, parts: &[Set], ss: &SetSystem) -> io::Result<()> {
+    let crossed = all_crossed_parts(parts, ss);
+    let indices: Vec<usize> = ss
+        .sets
+        .iter()
+        .map(|s| s.index)
+        .chain(ss.sets.len()..ss.sets.len() + ss.ranges.len())
+        .collect();
+    let mut file = BufWriter::new(fs::File::create(filename)?);
+    if is_json(filename) {
+        writeln!(file, "[")?;
+        for (i, (s, c)) in indices.iter().zip(crossed.iter()).enumerate() {
+            let sep = if i + 1 < indices.len() { "," } else { "" };
+            writeln!(
+                file,
+                "  {{\"set\": {}, \"crossings\": {}, \"parts\": [{}]}}{}",
+                s,
+                c.len(),
+                join(c, ", "),
+                sep
+            )?;
+        }
+        writeln!(file, "]")?;
+    } else {
+        writeln!(file, "set,crossings,parts")?;
+        for (s, c) in indices.iter().zip(crossed.iter()) {
+            writeln!(file, "{},{},{}", s, c.len(), join(c, " "))?;
+        }
+    }
+    file.flush()
+}
+
+/// Writes the number of sets crossing each number of parts
+pub fn write_histogram(filename: &str, intersections: &[i32]) -> io::Result<()> {
+    let hist = histogram(intersections);
+    let mut file = BufWriter::new(fs::File::create(filename)?);
+    if is_json(filename) {
+        let entries: Vec<String> = hist
+            .iter()
+            .enumerate()
+            .map(|(k, c)| format!("{{\"crossings\": {}, \"sets\": {}}}", k, c))
+            .collect();
+        writeln!(file, "[{}]", entries.join(", "))?;
+    } else {
+        writeln!(file, "crossings,sets")?;
+        for (k, c) in hist.iter().enumerate() {
+            writeln!(file, "{},{}", k, c)?;
+        }
+    }
+    file.flush()
+}
+
+/// Writes the size, number of crossing sets, diameter and bounding box of
+/// every part
+pub fn write_parts(filename: &str, stats: &[PartStats]) -> io::Result<()> {
+    let floats = |v: &[f32], sep: &str| {
+        v.iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join(sep)
+    };
+    let mut file = BufWriter::new(fs::File::create(filename)?);
+    if is_json(filename) {
+        writeln!(file, "[")?;
+        for (i, p) in stats.iter().enumerate() {
+            let sep = if i + 1 < stats.len() { "," } else { "" };
+            writeln!(
+                file,
+                "  {{\"part\": {}, \"size\": {}, \"crossings\": {}, \"diameter\": {}, \"bbox_min\": [{}], \"bbox_max\": [{}]}}{}",
+                p.part,
+                p.size,
+                p.crossings,
+                p.diameter,
+                floats(&p.bbox_min, ", "),
+                floats(&p.bbox_max, ", "),
+                sep
+            )?;
+        }
+        writeln!(file, "]")?;
+    } else {
+        writeln!(file, "part,size,crossings,diameter,bbox_min,bbox_max")?;
+        for p in stats.iter() {
+            writeln!(
+                file,
+                "{},{},{},{},{},{}",
+                p.part,
+                p.size,
+                p.crossings,
+                p.diameter,
+                floats(&p.bbox_min, " "),
+                floats(&p.bbox_max, " ")
+            )?;
+        }
+    }
+    file.flush()
+}
+
+/// Summary of a partitioning run, one row of a results file
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub algorithm: String,
+    pub setsystem: String,
+    pub n: usize,
+    pub m: usize,
+    pub d: usize,
+    pub t: usize,
+    pub seed: u64,
+    /// Number of warmup rounds, for the algorithms that have some
+    pub warmup: Option<i32>,
+    pub max: i32,
+    pub avg: f32,
+    pub min: i32,
+    pub adjacency_time: Option<Duration>,
+    pub partition_time: Duration,
+    /// Peak memory in bytes
+    pub peak_memory: Option<u64>,
+    pub weight_doublings: Option<u64>,
+    pub max_set_weight: Option<u32>,
+    /// Ratios of `max` to `t^(1-1/d)` and to `sqrt(t)`
+    pub ratio_d: f64,
+    pub ratio_sqrt: f64,
+    /// Dual shatter dimension and ratio of `max` to `t^(1-1/shatter_dim)`
+    pub shatter_dim: f64,
+    pub ratio_shatter: f64,
+    /// Git revision of the build
+    pub revision: Option<String>,
+}
+
+const RUN_COLUMNS: [&str; 21] = [
+    "algorithm",
+    "setsystem",
+    "n",
+    "m",
+    "d",
+    "t",
+    "seed",
+    "warmup",
+    "max",
+    "avg",
+    "min",
+    "adjacency_time",
+    "partition_time",
+    "peak_memory",
+    "weight_doublings",
+    "max_set_weight",
+    "ratio_d",
+    "ratio_sqrt",
+    "shatter_dim",
+    "ratio_shatter",
+    "revision",
+];
+
+/// Value of a column of a results file
+enum Value {
+    /// Quoted in JSON
+    Text(String),
+    /// Written as is
+    Number(String),
+}
+
+fn number(x: impl ToString) -> Option<Value> {
+    Some(Value::Number(x.to_string()))
+}
+
+/// Value of a ratio column, missing if it is not finite as JSON has no
+/// infinity or NaN
+fn ratio(x: f64) -> Option<Value> {
+    x.is_finite().then(|| Value::Number(format!("{:.4}", x)))
+}
+
+fn json_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+impl Run {
+    /// Values of the columns, missing ones being `None`
+    fn values(&self) -> [Option<Value>; 21] {
+        let text = |s: &str| Some(Value::Text(s.to_string()));
+        let secs = |d: Duration| Value::Number(format!("{:.4}", d.as_secs_f64()));
+        [
+            text(&self.algorithm),
+            text(&self.setsystem),
+            number(self.n),
+            number(self.m),
+            number(self.d),
+            number(self.t),
+            number(self.seed),
+            self.warmup.and_then(number),
+            number(self.max),
+            Some(self.avg).filter(|x| x.is_finite()).and_then(number),
+            number(self.min),
+            self.adjacency_time.map(secs),
+            Some(secs(self.partition_time)),
+            self.peak_memory.and_then(number),
+            self.weight_doublings.and_then(number),
+            self.max_set_weight.and_then(number),
+            ratio(self.ratio_d),
+            ratio(self.ratio_sqrt),
+            ratio(self.shatter_dim),
+            ratio(self.ratio_shatter),
+            self.revision.as_deref().and_then(text),
+        ]
+    }
+}
+
+/// Appends `run` to a results file, creating it with a header row if it
+/// does not exist or is empty
+///
+/// Fails if a CSV file starts with other columns than those of the runs.
+pub fn append_run(filename: &str, run: &Run) -> io::Result<()> {
+    let jsonl = filename.ends_with(".jsonl");
+    let header = RUN_COLUMNS.join(",");
+    if !jsonl {
+        if let Ok(f) = fs::File::open(filename) {
+            let mut first = String::new();
+            BufReader::new(f).read_line(&mut first)?;
+            if !first.is_empty() && first.trim_end_matches(['\r', '\n']) != header {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} does not have the columns {}", filename, header),
+                ));
+            }
+        }
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(filename)?;
+    let values = run.values();
+    if jsonl {
+        let fields: Vec<String> = RUN_COLUMNS
+            .iter()
+            .zip(values.iter())
+            .map(|(c, v)| {
+                let v = match v {
+                    None => "null".to_string(),
+                    Some(Value::Text(v)) => json_string(v),
+                    Some(Value::Number(v)) => v.clone(),
+                };
+                format!("\"{}\": {}", c, v)
+            })
+            .collect();
+        writeln!(file, "{{{}}}", fields.join(", "))
+    } else {
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{}", header)?;
+        }
+        let fields: Vec<String> = values
+            .iter()
+            .map(|v| match v {
+                None => String::new(),
+                Some(Value::Text(v)) | Some(Value::Number(v)) => csv_field(v),
+            })
+            .collect();
+        writeln!(file, "{}", fields.join(","))
+    }
+}
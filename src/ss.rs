@@ -1,8 +1,14 @@
+use crate::binary;
+use crate::bitset::BitSet;
+use crate::geometry::{combinations, Hyperplane};
+use crate::range::Range;
 use num_integer::Roots;
 use rand::seq::SliceRandom;
-use rulinalg::matrix::decomposition::PartialPivLu;
-use rulinalg::matrix::Matrix;
-use std::{fs, io::BufWriter, io::Write};
+use rand::Rng;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::{error, fmt, fs, io, io::BufWriter, io::Write};
 
 #[derive(Debug, Clone)]
 pub struct Point {
@@ -24,7 +30,7 @@ pub struct Point {
 
 #[derive(Debug)]
 pub struct Set {
-    pub points: Vec<bool>,
+    pub points: BitSet,
     // pub weight: i32,
     pub index: usize,
 }
@@ -55,107 +61,535 @@ pub struct Set {
 //     }
 // }
 
+/// Layout of a set system file
+///
+/// Both text formats start with one line of comma separated coordinates per
+/// point. In the dense format, a `sets` line is followed by one row of 0/1
+/// cells per set. In the sparse format, a `sets sparse` line is followed by
+/// one row per set listing either the indices of its points after a `+`, or
+/// the indices of the points it does not contain after a `-`. The binary
+/// format is described in the `binary` module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Dense,
+    Sparse,
+    Binary,
+}
+
+impl Format {
+    /// Binary for `.bin` files, dense text otherwise
+    pub fn from_path(filename: &str) -> Format {
+        if filename.ends_with(".bin") {
+            Format::Binary
+        } else {
+            Format::Dense
+        }
+    }
+}
+
+/// Error returned when a set system file cannot be read
+#[derive(Debug)]
+pub enum ParseError {
+    /// The file could not be read
+    Io(io::Error),
+    /// A coordinate or a set cell is not a valid value
+    InvalidToken {
+        line: usize,
+        column: usize,
+        token: String,
+    },
+    /// A point does not have the same dimension as the first point
+    Dimension {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// A set row does not have one cell per point
+    RowLength {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// A range does not have the number of values its kind and the
+    /// dimension require
+    RangeLength {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// The `sets` (or `sets sparse`) line separating points from sets is
+    /// missing
+    MissingSeparator,
+    /// A mandatory line of a partition file is missing
+    MissingSection(&'static str),
+    /// The binary header does not start with the magic bytes
+    BadMagic,
+    /// The binary format version is not supported
+    UnsupportedVersion(u16),
+    /// The binary coordinate type is not supported
+    UnsupportedCoordinates(u8),
+    /// The binary file is shorter or longer than announced by its header
+    Truncated,
+}
+
+impl ParseError {
+    pub(crate) fn invalid_token(line: usize, column: usize, token: &str) -> ParseError {
+        ParseError::InvalidToken {
+            line,
+            column,
+            token: token.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "{}", e),
+            ParseError::InvalidToken {
+                line,
+                column,
+                token,
+            } => write!(
+                f,
+                "line {}, column {}: invalid token {:?}",
+                line, column, token
+            ),
+            ParseError::Dimension {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: point has dimension {}, expected {}",
+                line, found, expected
+            ),
+            ParseError::RowLength {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: set has {} cells, expected one per point ({})",
+                line, found, expected
+            ),
+            ParseError::RangeLength {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: range has {} values, expected {}",
+                line, found, expected
+            ),
+            ParseError::MissingSeparator => write!(f, "missing \"sets\" separator line"),
+            ParseError::MissingSection(s) => write!(f, "missing \"{}\" line", s),
+            ParseError::BadMagic => write!(f, "not a binary set system file"),
+            ParseError::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            ParseError::UnsupportedCoordinates(c) => {
+                write!(f, "unsupported coordinate type {}", c)
+            }
+            ParseError::Truncated => write!(f, "file size does not match its header"),
+        }
+    }
+}
+
+impl error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> ParseError {
+        ParseError::Io(e)
+    }
+}
+
+/// Splits a comma separated row into `(column, token)` pairs, ignoring the
+/// trailing comma written by `to_file`
+pub(crate) fn cells(l: &str) -> Vec<(usize, &str)> {
+    let mut res = Vec::new();
+    let mut column = 1;
+    for x in l.split(',') {
+        res.push((column, x));
+        column += x.len() + 1;
+    }
+    if let Some((_, "")) = res.last() {
+        res.pop();
+    }
+    res
+}
+
+/// `n` points drawn uniformly in the unit cube of dimension `d`
+fn random_points<R: Rng + ?Sized>(n: i32, d: usize, rng: &mut R) -> Vec<Point> {
+    let mut points = Vec::new();
+    for i in 0..n {
+        let mut temp = vec![0.; d];
+        for x in temp.iter_mut() {
+            *x = rng.gen::<f32>();
+        }
+        points.push(Point {
+            coordinates: temp,
+            index: i as usize,
+        });
+    }
+    points
+}
+
+/// Number of samples of `d` points tried by `random_hyperplane` before
+/// perturbing them, and then number of perturbed samples tried
+const HYPERPLANE_ATTEMPTS: usize = 100;
+
+/// Hyperplane through `d` random points of `points`
+///
+/// Affinely dependent samples are drawn again, and if the points do not span
+/// the space at all, the sampled points are slightly perturbed until they do.
+///
+/// # Panics
+///
+/// If `d` is zero or no perturbed sample spans a hyperplane.
+fn random_hyperplane<R: Rng + ?Sized>(points: &[Point], d: usize, rng: &mut R) -> Hyperplane {
+    assert!(d > 0, "no hyperplane through points of dimension 0");
+    for _ in 0..HYPERPLANE_ATTEMPTS {
+        let sample: Vec<&[f32]> = points
+            .choose_multiple(rng, d)
+            .map(|p| &p.coordinates[..])
+            .collect();
+        if sample.len() == d {
+            if let Some(h) = Hyperplane::through(&sample) {
+                return h;
+            }
+        }
+    }
+    for _ in 0..HYPERPLANE_ATTEMPTS {
+        let sample: Vec<Vec<f32>> = (0..d)
+            .map(|_| {
+                points[rng.gen_range(0..points.len())]
+                    .coordinates
+                    .iter()
+                    .map(|x| x + rng.gen_range(-1e-3..1e-3))
+                    .collect()
+            })
+            .collect();
+        let sample: Vec<&[f32]> = sample.iter().map(|p| &p[..]).collect();
+        if let Some(h) = Hyperplane::through(&sample) {
+            return h;
+        }
+    }
+    panic!(
+        "no hyperplane through {} perturbed points after {} attempts",
+        d, HYPERPLANE_ATTEMPTS
+    );
+}
+
+/// Adjacency lists indexed by point or by set
+pub type Adjacency = Vec<Vec<usize>>;
+
+/// Size distribution of the boxes drawn by `SetSystem::boxes`
+#[derive(Debug, Clone, Copy)]
+pub enum BoxShape {
+    /// Boxes spanned by two uniform corners of the unit cube
+    Uniform,
+    /// Boxes of the given positive volume, with side ratios of at most 4 and
+    /// a uniform position in the unit cube
+    ///
+    /// Sides are capped at 1 so that the boxes fit in the cube, which makes
+    /// boxes with a side longer than 1 smaller than the requested volume.
+    Volume(f32),
+}
+
+/// Where the centres of the balls drawn by `SetSystem::balls` are placed
+#[derive(Debug, Clone, Copy)]
+pub enum BallCentre {
+    /// On a random point of the set system
+    Point,
+    /// Uniformly in the unit cube
+    Uniform,
+}
+
 pub struct SetSystem {
     pub points: Vec<Point>,
     pub sets: Vec<Set>,
+    /// Sets given by a predicate on the coordinates, following `sets`, see
+    /// `SetSystem::materialize`
+    pub ranges: Vec<Range>,
 }
 
 impl SetSystem {
-    pub fn grid(n: i32, d: usize) -> SetSystem {
-        let mut points = Vec::new();
-        for i in 0..n {
-            let mut temp = vec![0.; d];
-            for j in 0..d {
-                temp[j] = rand::random::<f32>();
-            }
-            points.push(Point {
-                coordinates: temp,
-                index: i as usize,
-            });
-        }
+    /// Axis-parallel slabs over `n` uniform points of the unit cube, with
+    /// `n^(1/d) - 1` thresholds per axis
+    pub fn grid<R: Rng + ?Sized>(n: i32, d: usize, rng: &mut R) -> SetSystem {
+        let thresholds = (n.nth_root(d as u32) as usize).saturating_sub(1);
+        SetSystem::slabs(n, d, thresholds, false, rng)
+    }
+
+    /// Axis-parallel halfspaces over `n` uniform points of the unit cube
+    ///
+    /// Each axis is cut at `thresholds` evenly spaced values `c` and both
+    /// `x_k > c` and its complement `x_k <= c` are added. With `two_sided`,
+    /// the slabs `a < x_k <= b` between any two thresholds of an axis and
+    /// their complements are added as well.
+    pub fn slabs<R: Rng + ?Sized>(
+        n: i32,
+        d: usize,
+        thresholds: usize,
+        two_sided: bool,
+        rng: &mut R,
+    ) -> SetSystem {
+        let points = random_points(n, d, rng);
+        let cuts: Vec<f32> = (1..=thresholds)
+            .map(|i| i as f32 / (thresholds + 1) as f32)
+            .collect();
         let mut sets = Vec::new();
-        let mut index: usize = 0;
-        for k in 0..d {
-            for i in 0..n.nth_root(d as u32) {
-                let mut temp: Vec<bool> = vec![false; n as usize];
-                let mut temp2: Vec<bool> = vec![false; n as usize];
-                for p in points.iter() {
-                    temp[p.index] =
-                        p.coordinates[k] * f32::powf(n as f32, 1.0 / (d as f32)) > i as f32;
-                    temp2[p.index] =
-                        p.coordinates[k] * f32::powf(n as f32, 1.0 / (d as f32)) > i as f32;
+        let push = |sets: &mut Vec<Set>, inside: &dyn Fn(&Point) -> bool| {
+            let mut temp = BitSet::new(n as usize);
+            let mut temp2 = BitSet::new(n as usize);
+            for p in points.iter() {
+                if inside(p) {
+                    temp.insert(p.index);
+                } else {
+                    temp2.insert(p.index);
                 }
+            }
+            for points in [temp, temp2] {
                 sets.push(Set {
-                    points: temp,
-                    index,
-                });
-                index += 1;
-                sets.push(Set {
-                    points: temp2,
-                    index,
+                    points,
+                    index: sets.len(),
                 });
-                index += 1;
+            }
+        };
+        for k in 0..d {
+            for c in cuts.iter() {
+                push(&mut sets, &|p| p.coordinates[k] > *c);
+            }
+            if two_sided {
+                for (i, a) in cuts.iter().enumerate() {
+                    for b in cuts[i + 1..].iter() {
+                        push(&mut sets, &|p| {
+                            *a < p.coordinates[k] && p.coordinates[k] <= *b
+                        });
+                    }
+                }
             }
         }
-        SetSystem { points, sets }
+        SetSystem {
+            points,
+            sets,
+            ranges: Vec::new(),
+        }
+    }
+
+    /// `m / 2` random hyperplanes through `d` of `n` uniform points of the
+    /// unit cube, each giving the points above it and its complement
+    ///
+    /// # Panics
+    ///
+    /// If `d` is zero.
+    pub fn rhs<R: Rng + ?Sized>(n: i32, m: i32, d: usize, rng: &mut R) -> SetSystem {
+        let mut ss = SetSystem::rhs_ranges(n, m, d, rng);
+        ss.materialize();
+        ss
     }
 
-    pub fn rhs(n: i32, m: i32, d: usize) -> SetSystem {
-        let mut points = Vec::new();
-        for i in 0..n {
-            let mut temp = vec![0.; d];
-            for j in 0..d {
-                temp[j] = rand::random::<f32>();
+    /// `SetSystem::rhs` with the halfspaces kept as ranges
+    pub fn rhs_ranges<R: Rng + ?Sized>(n: i32, m: i32, d: usize, rng: &mut R) -> SetSystem {
+        assert!(d > 0, "random hyperplanes need a positive dimension");
+        let points = random_points(n, d, rng);
+        let mut ranges = Vec::new();
+        if !points.is_empty() {
+            for _ in 0..m / 2 {
+                let h = Range::Halfspace(random_hyperplane(&points, d, rng));
+                ranges.push(h.clone());
+                ranges.push(Range::Complement(Box::new(h)));
             }
-            points.push(Point {
-                coordinates: temp,
-                index: i as usize,
-            });
         }
-        let mut sets = Vec::new();
-        for j in 0..(m / 2) as usize {
-            let sample: Vec<_> = points.choose_multiple(&mut rand::thread_rng(), d).collect();
-            let mut v = Vec::new();
-            for s in sample.iter() {
-                v.extend(s.coordinates.clone());
-            }
-            let mat = Matrix::new(d, d, v);
-            let b = vector![1.0;d];
-            let lu = PartialPivLu::decompose(mat).expect("Matrix is invertible");
-            let y = lu.solve(b).expect("Matrix is invertible.");
-            let mut set = vec![false; points.len()];
-            let mut set_c = vec![false; points.len()];
-            for (i, p) in points.iter().enumerate() {
-                let mut temp = 0.0;
-                for k in 0..d {
-                    temp += p.coordinates[k] as f32 * y[k]
-                }
-                if temp > 1.0 {
-                    set[i] = true;
-                } else {
-                    set_c[i] = true;
+        SetSystem {
+            points,
+            sets: Vec::new(),
+            ranges,
+        }
+    }
+
+    /// `m` random axis-parallel boxes over `n` uniform points of the unit cube
+    pub fn boxes<R: Rng + ?Sized>(
+        n: i32,
+        m: i32,
+        d: usize,
+        shape: BoxShape,
+        rng: &mut R,
+    ) -> SetSystem {
+        let mut ss = SetSystem::box_ranges(n, m, d, shape, rng);
+        ss.materialize();
+        ss
+    }
+
+    /// `SetSystem::boxes` with the boxes kept as ranges
+    pub fn box_ranges<R: Rng + ?Sized>(
+        n: i32,
+        m: i32,
+        d: usize,
+        shape: BoxShape,
+        rng: &mut R,
+    ) -> SetSystem {
+        let points = random_points(n, d, rng);
+        let mut ranges = Vec::new();
+        for _ in 0..m {
+            let mut low = vec![0.; d];
+            let mut high = vec![0.; d];
+            match shape {
+                BoxShape::Uniform => {
+                    for k in 0..d {
+                        let (a, b) = (rng.gen::<f32>(), rng.gen::<f32>());
+                        low[k] = a.min(b);
+                        high[k] = a.max(b);
+                    }
+                }
+                BoxShape::Volume(v) => {
+                    assert!(v > 0., "box volume {} is not positive", v);
+                    let logs: Vec<f32> = (0..d)
+                        .map(|_| rng.gen_range(-1.0..1.0) * std::f32::consts::LN_2)
+                        .collect();
+                    let mean = logs.iter().sum::<f32>() / d as f32;
+                    for k in 0..d {
+                        let side = (v.powf(1.0 / d as f32) * (logs[k] - mean).exp()).min(1.0);
+                        low[k] = rng.gen::<f32>() * (1.0 - side);
+                        high[k] = low[k] + side;
+                    }
                 }
             }
-            sets.push(Set {
-                points: set,
-                index: 2 * j,
-            });
-            sets.push(Set {
-                points: set_c,
-                index: 2 * j + 1,
-            });
+            ranges.push(Range::Box { low, high });
         }
-        SetSystem { points, sets }
+        SetSystem {
+            points,
+            sets: Vec::new(),
+            ranges,
+        }
+    }
+
+    /// `m` Euclidean balls and their complements over `n` uniform points of
+    /// the unit cube
+    ///
+    /// Each ball is the `fraction * n` points closest to its centre; when
+    /// `fraction` is `None`, a uniform fraction is drawn for every ball.
+    pub fn balls<R: Rng + ?Sized>(
+        n: i32,
+        m: i32,
+        d: usize,
+        fraction: Option<f32>,
+        centre: BallCentre,
+        rng: &mut R,
+    ) -> SetSystem {
+        let mut ss = SetSystem::ball_ranges(n, m, d, fraction, centre, rng);
+        ss.materialize();
+        ss
     }
 
-    pub fn build_adjacency(
-        &self,
-    ) -> (
-        Vec<Vec<usize>>,
-        Vec<Vec<usize>>,
-        Vec<Vec<usize>>,
-        Vec<Vec<usize>>,
-    ) {
+    /// `SetSystem::balls` with the balls kept as ranges, each ball being
+    /// bounded by its `fraction * n`-th closest point
+    pub fn ball_ranges<R: Rng + ?Sized>(
+        n: i32,
+        m: i32,
+        d: usize,
+        fraction: Option<f32>,
+        centre: BallCentre,
+        rng: &mut R,
+    ) -> SetSystem {
+        let points = random_points(n, d, rng);
+        let mut ranges = Vec::new();
+        if points.is_empty() {
+            return SetSystem {
+                points,
+                sets: Vec::new(),
+                ranges,
+            };
+        }
+        for _ in 0..m / 2 {
+            let c: Vec<f32> = match centre {
+                BallCentre::Point => points[rng.gen_range(0..points.len())].coordinates.clone(),
+                BallCentre::Uniform => (0..d).map(|_| rng.gen::<f32>()).collect(),
+            };
+            let f = fraction.unwrap_or_else(|| rng.gen::<f32>());
+            let k = ((f * n as f32).round() as usize).clamp(1, points.len());
+            let mut dist: Vec<f32> = points
+                .iter()
+                .map(|p| {
+                    p.coordinates
+                        .iter()
+                        .zip(c.iter())
+                        .map(|(x, y)| (x - y) * (x - y))
+                        .sum()
+                })
+                .collect();
+            let (_, squared_radius, _) = dist.select_nth_unstable_by(k - 1, |a, b| a.total_cmp(b));
+            let ball = Range::Ball {
+                centre: c,
+                squared_radius: *squared_radius,
+            };
+            ranges.push(ball.clone());
+            ranges.push(Range::Complement(Box::new(ball)));
+        }
+        SetSystem {
+            points,
+            sets: Vec::new(),
+            ranges,
+        }
+    }
+
+    /// Every combinatorially distinct halfspace over `n` uniform points of
+    /// the unit cube, see `SetSystem::enumerate_halfspaces`
+    pub fn halfspaces<R: Rng + ?Sized>(n: i32, d: usize, rng: &mut R) -> SetSystem {
+        let points = random_points(n, d, rng);
+        let sets = SetSystem::enumerate_halfspaces(&points);
+        SetSystem {
+            points,
+            sets,
+            ranges: Vec::new(),
+        }
+    }
+
+    /// All distinct subsets of `points` cut by a halfspace, including the
+    /// empty and the full set
+    ///
+    /// In the plane, the subsets are enumerated by an angular sweep around
+    /// every point, see `halfplanes`, which is exact even for collinear or
+    /// equal points. In higher dimensions, every such subset
+    /// is cut by a hyperplane through `d` of the points, each of them being
+    /// put on either side, so the O(n^d) hyperplanes through `d` points are
+    /// enumerated. This is exact for points in general position; when more
+    /// than `d` points lie on a hyperplane, only the subsets with all of them
+    /// on the same side are produced.
+    pub fn enumerate_halfspaces(points: &[Point]) -> Vec<Set> {
+        match points.first().map_or(0, |p| p.coordinates.len()) {
+            2 => halfplanes(points),
+            _ => halfspaces_through_points(points),
+        }
+    }
+
+    /// Stores the ranges as sets, after the other sets, for the algorithms
+    /// needing the points of every set
+    pub fn materialize(&mut self) {
+        let start = self.sets.len();
+        let points = &self.points;
+        let sets: Vec<Set> = self
+            .ranges
+            .par_iter()
+            .enumerate()
+            .map(|(i, r)| Set {
+                points: r.to_bitset(points),
+                index: start + i,
+            })
+            .collect();
+        self.sets.extend(sets);
+        self.ranges.clear();
+    }
+
+    /// Sets containing every point, sets not containing it, points of every
+    /// set and points not in it, see `Incidence` for a compact alternative
+    pub fn build_adjacency(&self) -> (Adjacency, Adjacency, Adjacency, Adjacency) {
         let mut sets_adj = vec![Vec::<usize>::new(); self.sets.len()];
         let mut points_adj = vec![Vec::<usize>::new(); self.points.len()];
         let mut sets_adj_complement = vec![Vec::<usize>::new(); self.sets.len()];
@@ -180,70 +614,564 @@ impl SetSystem {
         )
     }
 
-    pub fn from_file(filename: &str) -> SetSystem {
-        let content = fs::read_to_string(filename).expect("Should have been able to read the file");
-        let lines = content.split("\n");
-        let mut points = Vec::new();
+    /// FNV-1a hash of the points and sets, identifying the set system a
+    /// partition was computed on
+    pub fn fingerprint(&self) -> u64 {
+        let mut h: u64 = 0xcbf29ce484222325;
+        let mut feed = |bytes: &[u8]| {
+            for b in bytes {
+                h ^= *b as u64;
+                h = h.wrapping_mul(0x100000001b3);
+            }
+        };
+        feed(&(self.points.len() as u64).to_le_bytes());
+        for p in self.points.iter() {
+            for c in p.coordinates.iter() {
+                feed(&c.to_le_bytes());
+            }
+        }
+        //Ranges are hashed as sets, so that materializing them keeps the
+        //fingerprint
+        feed(&((self.sets.len() + self.ranges.len()) as u64).to_le_bytes());
+        for s in self.sets.iter() {
+            for w in s.points.words() {
+                feed(&w.to_le_bytes());
+            }
+        }
+        for r in self.ranges.iter() {
+            for w in r.to_bitset(&self.points).words() {
+                feed(&w.to_le_bytes());
+            }
+        }
+        h
+    }
+
+    /// Reads a set system in any of the `Format`s, binary files being
+    /// recognized by their magic bytes
+    pub fn from_file(filename: &str) -> Result<SetSystem, ParseError> {
+        let bytes = fs::read(filename)?;
+        if bytes.starts_with(binary::MAGIC) {
+            return binary::read(&bytes);
+        }
+        let content =
+            String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut points: Vec<Point> = Vec::new();
         let mut sets = Vec::new();
-        let mut set = false;
-        let mut set_index = 0;
-        let mut pt_index = 0;
-        for l in lines {
-            if !l.is_empty() {
-                if l == "sets" {
-                    set = true;
-                } else if set {
-                    sets.push(Set {
-                        index: set_index,
-                        points: l
-                            .split(",")
-                            .filter_map(|x| match x {
-                                "0" => Some(false),
-                                "1" => Some(true),
-                                _ => None,
-                            })
-                            .collect::<Vec<bool>>(),
+        let mut ranges = Vec::new();
+        let mut format = None;
+        let mut in_ranges = false;
+        for (line, l) in content.split('\n').enumerate() {
+            let line = line + 1;
+            let l = l.trim_end_matches('\r');
+            if l.is_empty() {
+                continue;
+            }
+            if l == "ranges" {
+                in_ranges = true;
+            } else if in_ranges {
+                let d = points.first().map_or(0, |p| p.coordinates.len());
+                ranges.push(Range::parse(line, l, d)?);
+            } else if l == "sets" {
+                format = Some(Format::Dense);
+            } else if l == "sets sparse" {
+                format = Some(Format::Sparse);
+            } else if format == Some(Format::Sparse) {
+                let (complement, rest) = match (l.strip_prefix('+'), l.strip_prefix('-')) {
+                    (Some(rest), _) => (false, rest),
+                    (_, Some(rest)) => (true, rest),
+                    _ => return Err(ParseError::invalid_token(line, 1, cells(l)[0].1)),
+                };
+                let mut row = BitSet::new(points.len());
+                for (column, x) in cells(rest) {
+                    match x.parse::<usize>() {
+                        Ok(i) if i < points.len() => row.insert(i),
+                        _ => return Err(ParseError::invalid_token(line, column + 1, x)),
+                    }
+                }
+                sets.push(Set {
+                    index: sets.len(),
+                    points: if complement { row.complement() } else { row },
+                });
+            } else if format.is_some() {
+                let mut row = Vec::with_capacity(points.len());
+                for (column, x) in cells(l) {
+                    row.push(match x {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err(ParseError::invalid_token(line, column, x)),
                     });
-                    set_index += 1;
-                } else {
-                    points.push(Point {
-                        index: pt_index,
-                        coordinates: l
-                            .split(",")
-                            .filter_map(|x| match x {
-                                "" => None,
-                                x => Some(x.parse::<f32>().expect("Fail to parse")),
-                            })
-                            .collect::<Vec<f32>>()
-                            .try_into()
-                            .unwrap(),
+                }
+                if row.len() != points.len() {
+                    return Err(ParseError::RowLength {
+                        line,
+                        expected: points.len(),
+                        found: row.len(),
                     });
-                    pt_index += 1;
                 }
+                sets.push(Set {
+                    index: sets.len(),
+                    points: row.into_iter().collect(),
+                });
+            } else {
+                let mut coordinates = Vec::new();
+                for (column, x) in cells(l) {
+                    match x.parse::<f32>() {
+                        Ok(c) => coordinates.push(c),
+                        Err(_) => return Err(ParseError::invalid_token(line, column, x)),
+                    }
+                }
+                if let Some(p) = points.first() {
+                    if p.coordinates.len() != coordinates.len() {
+                        return Err(ParseError::Dimension {
+                            line,
+                            expected: p.coordinates.len(),
+                            found: coordinates.len(),
+                        });
+                    }
+                }
+                points.push(Point {
+                    index: points.len(),
+                    coordinates,
+                });
             }
         }
+        if format.is_none() && !in_ranges {
+            return Err(ParseError::MissingSeparator);
+        }
+
+        Ok(SetSystem {
+            points,
+            sets,
+            ranges,
+        })
+    }
 
-        SetSystem { points, sets }
+    /// Writes the set system in the dense text format
+    pub fn to_file(&self, filename: &str) {
+        self.to_file_as(filename, Format::Dense)
     }
 
-    pub fn to_file(&self, filename: &str) -> () {
+    pub fn to_file_as(&self, filename: &str, format: Format) {
         let mut file = BufWriter::new(fs::File::create(filename).expect("Fail to create file"));
-        for x in self.points.iter() {
-            for c in x.coordinates.iter() {
-                file.write(c.to_string().as_bytes()).expect("Fail to write");
-                file.write(b",").expect("Fail to write");
+        if format != Format::Binary {
+            for x in self.points.iter() {
+                for c in x.coordinates.iter() {
+                    file.write_all(c.to_string().as_bytes())
+                        .expect("Fail to write");
+                    file.write_all(b",").expect("Fail to write");
+                }
+                file.write_all(b"\n").expect("Fail to write");
+            }
+        }
+        match format {
+            Format::Dense => {
+                file.write_all(b"sets\n").expect("Fail to write");
+                for x in self.sets.iter() {
+                    for c in x.points.iter() {
+                        file.write_all((c as i8).to_string().as_bytes())
+                            .expect("Fail to write");
+                        file.write_all(b",").expect("Fail to write");
+                    }
+                    file.write_all(b"\n").expect("Fail to write");
+                }
+            }
+            Format::Sparse => {
+                file.write_all(b"sets sparse\n").expect("Fail to write");
+                for x in self.sets.iter() {
+                    let (sign, indices) = if 2 * x.points.count_ones() <= x.points.len() {
+                        (b"+", x.points.clone())
+                    } else {
+                        (b"-", x.points.complement())
+                    };
+                    file.write_all(sign).expect("Fail to write");
+                    for i in indices.ones() {
+                        file.write_all(i.to_string().as_bytes())
+                            .expect("Fail to write");
+                        file.write_all(b",").expect("Fail to write");
+                    }
+                    file.write_all(b"\n").expect("Fail to write");
+                }
+            }
+            Format::Binary => {
+                file.write_all(&binary::write(self)).expect("Fail to write");
             }
-            file.write(b"\n").expect("Fail to write");
         }
-        file.write(b"sets\n").expect("Fail to write");
-        for x in self.sets.iter() {
-            for c in x.points.iter() {
-                file.write((*c as i8).to_string().as_bytes())
-                    .expect("Fail to write");
-                file.write(b",").expect("Fail to write");
+        if format != Format::Binary && !self.ranges.is_empty() {
+            file.write_all(b"ranges\n").expect("Fail to write");
+            for r in self.ranges.iter() {
+                writeln!(file, "{}", r).expect("Fail to write");
             }
-            file.write(b"\n").expect("Fail to write");
         }
+        file.flush().expect("Fail to write");
         println!("Set system written to file: {}", filename);
     }
 }
+
+/// Subsets of `points` cut by a hyperplane through `d` of them, see
+/// `SetSystem::enumerate_halfspaces`
+fn halfspaces_through_points(points: &[Point]) -> Vec<Set> {
+    let n = points.len();
+    let d = points.first().map_or(0, |p| p.coordinates.len());
+    let mut seen = HashSet::new();
+    let mut sets = Vec::new();
+    let mut push = |set: BitSet| {
+        if seen.insert(set.clone()) {
+            sets.push(Set {
+                index: sets.len(),
+                points: set,
+            });
+        }
+    };
+    push(BitSet::new(n));
+    push((0..n).map(|_| true).collect());
+    for c in combinations(n, d) {
+        let sample: Vec<&[f32]> = c.iter().map(|i| &points[*i].coordinates[..]).collect();
+        let h = match Hyperplane::through(&sample) {
+            Some(h) => h,
+            None => continue,
+        };
+        let mut above = BitSet::new(n);
+        let mut below = BitSet::new(n);
+        let mut on = Vec::new();
+        for (i, p) in points.iter().enumerate() {
+            match h.side(&p.coordinates) {
+                1 => above.insert(i),
+                -1 => below.insert(i),
+                _ => on.push(i),
+            }
+        }
+        let subsets: Vec<Vec<usize>> = if on.len() <= d {
+            (0..1 << on.len())
+                .map(|mask: usize| {
+                    on.iter()
+                        .enumerate()
+                        .filter(|(k, _)| mask >> k & 1 == 1)
+                        .map(|(_, i)| *i)
+                        .collect()
+                })
+                .collect()
+        } else {
+            vec![Vec::new(), on]
+        };
+        for subset in subsets {
+            let mut a = above.clone();
+            let mut b = below.clone();
+            for i in subset {
+                a.insert(i);
+                b.insert(i);
+            }
+            push(a);
+            push(b);
+        }
+    }
+    sets
+}
+
+/// Subsets of points of the plane cut by a halfplane, see
+/// `SetSystem::enumerate_halfspaces`
+///
+/// The nonempty proper subsets are exactly, for the ordered pairs `(p, q)` of
+/// points at distinct positions, the points at the position of `p`, those
+/// strictly left of the line from `p` to `q`, and those on the line behind
+/// `p`: tilting that line slightly around a point between `p` and the next
+/// point towards `q` separates them from the others. They are enumerated by
+/// sorting the directions from every `p`, equal directions being grouped by
+/// exact cross products, and sliding the half-turn window to the left of the
+/// line, in O(n^2 log n) time besides the output.
+fn halfplanes(points: &[Point]) -> Vec<Set> {
+    let n = points.len();
+    let mut seen = HashSet::new();
+    let mut sets = Vec::new();
+    let mut push = |set: BitSet| {
+        if seen.insert(set.clone()) {
+            sets.push(Set {
+                index: sets.len(),
+                points: set,
+            });
+        }
+    };
+    push(BitSet::new(n));
+    push((0..n).map(|_| true).collect());
+    for p in points.iter() {
+        //Points at the position of p are in every set of the sweep
+        let mut window = BitSet::new(n);
+        let mut around: Vec<((f64, f64), usize)> = Vec::new();
+        for (j, q) in points.iter().enumerate() {
+            let dx = q.coordinates[0] as f64 - p.coordinates[0] as f64;
+            let dy = q.coordinates[1] as f64 - p.coordinates[1] as f64;
+            if dx == 0. && dy == 0. {
+                window.insert(j);
+            } else {
+                around.push(((dx, dy), j));
+            }
+        }
+        around.sort_by(|a, b| angle_order(a.0, b.0));
+        //Points in the same direction from p, in angular order
+        let mut groups: Vec<((f64, f64), Vec<usize>)> = Vec::new();
+        for (v, j) in around {
+            match groups.last_mut() {
+                Some((u, g)) if angle_order(*u, v) == Ordering::Equal => g.push(j),
+                _ => groups.push((v, vec![j])),
+            }
+        }
+        let m = groups.len();
+        //Whether direction v is in the half-turn (u, u + PI]
+        let left = |u: (f64, f64), v: (f64, f64)| {
+            let cross = u.0 * v.1 - u.1 * v.0;
+            cross > 0. || (cross == 0. && u.0 * v.0 + u.1 * v.1 < 0.)
+        };
+        //The window holds the groups at positions a + 1 to end - 1
+        let mut end = 0;
+        for a in 0..m {
+            if end > a {
+                for j in groups[a].1.iter() {
+                    window.remove(*j);
+                }
+            } else {
+                end = a + 1;
+            }
+            while end < a + m && left(groups[a].0, groups[end % m].0) {
+                for j in groups[end % m].1.iter() {
+                    window.insert(*j);
+                }
+                end += 1;
+            }
+            push(window.clone());
+        }
+    }
+    sets
+}
+
+/// Order of the nonzero vectors `u` and `v` by angle in `[0, 2 PI)`
+fn angle_order(u: (f64, f64), v: (f64, f64)) -> Ordering {
+    let upper = |w: (f64, f64)| w.1 > 0. || (w.1 == 0. && w.0 > 0.);
+    let cross = u.0 * v.1 - u.1 * v.0;
+    upper(v).cmp(&upper(u)).then(if cross > 0. {
+        Ordering::Less
+    } else if cross < 0. {
+        Ordering::Greater
+    } else {
+        Ordering::Equal
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn halfplane_sweep_matches_hyperplanes_through_points() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for n in [0, 1, 2, 3, 40] {
+            let points = random_points(n, 2, &mut rng);
+            let sweep = halfplanes(&points);
+            let sweep_sets: HashSet<BitSet> = sweep.iter().map(|s| s.points.clone()).collect();
+            let expected: HashSet<BitSet> = halfspaces_through_points(&points)
+                .into_iter()
+                .map(|s| s.points)
+                .collect();
+            assert_eq!(
+                sweep.len(),
+                sweep_sets.len(),
+                "duplicate sets for n = {}",
+                n
+            );
+            assert_eq!(sweep_sets, expected, "n = {}", n);
+        }
+    }
+
+    fn plane(coordinates: &[(f32, f32)]) -> Vec<Point> {
+        coordinates
+            .iter()
+            .enumerate()
+            .map(|(index, (x, y))| Point {
+                coordinates: vec![*x, *y],
+                index,
+            })
+            .collect()
+    }
+
+    /// Subsets cut by a halfplane, as the prefixes of the points sorted along
+    /// a direction strictly between every two consecutive directions in which
+    /// two points have the same projection
+    fn brute_force_halfplanes(points: &[Point]) -> HashSet<BitSet> {
+        let n = points.len();
+        let mut critical = Vec::new();
+        for (i, p) in points.iter().enumerate() {
+            for q in points[i + 1..].iter() {
+                let dx = (q.coordinates[0] - p.coordinates[0]) as f64;
+                let dy = (q.coordinates[1] - p.coordinates[1]) as f64;
+                if dx != 0. || dy != 0. {
+                    let a = dy.atan2(dx) + std::f64::consts::FRAC_PI_2;
+                    critical.push(a.rem_euclid(std::f64::consts::TAU));
+                    critical.push((a + std::f64::consts::PI).rem_euclid(std::f64::consts::TAU));
+                }
+            }
+        }
+        critical.sort_by(f64::total_cmp);
+        critical.dedup_by(|a, b| (*a - *b).abs() < 1e-9);
+        let directions: Vec<f64> = match critical.len() {
+            0 => vec![0.],
+            k => (0..k)
+                .map(|i| {
+                    let next = critical.get(i + 1).copied();
+                    (critical[i] + next.unwrap_or(critical[0] + std::f64::consts::TAU)) / 2.
+                })
+                .collect(),
+        };
+        let mut res = HashSet::new();
+        res.insert(BitSet::new(n));
+        for a in directions {
+            let projection =
+                |p: &Point| p.coordinates[0] as f64 * a.cos() + p.coordinates[1] as f64 * a.sin();
+            let mut order: Vec<usize> = (0..n).collect();
+            order.sort_by(|x, y| projection(&points[*x]).total_cmp(&projection(&points[*y])));
+            let mut prefix = BitSet::new(n);
+            for (k, x) in order.iter().enumerate() {
+                prefix.insert(*x);
+                let tied = order
+                    .get(k + 1)
+                    .is_some_and(|y| points[*y].coordinates == points[*x].coordinates);
+                if !tied {
+                    res.insert(prefix.clone());
+                }
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn halfplane_sweep_handles_degenerate_points() {
+        let grid: Vec<(f32, f32)> = (0..9).map(|i| ((i % 3) as f32, (i / 3) as f32)).collect();
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut cases = vec![
+            vec![(0., 0.), (1., 0.), (2., 0.)],
+            grid,
+            vec![(0., 0.), (0., 0.), (1., 0.), (0., 1.)],
+            vec![(1., 1.); 3],
+        ];
+        for _ in 0..10 {
+            cases.push(
+                (0..12)
+                    .map(|_| (rng.gen_range(0..4) as f32, rng.gen_range(0..4) as f32))
+                    .collect(),
+            );
+        }
+        for case in cases {
+            let points = plane(&case);
+            let sweep: HashSet<BitSet> =
+                halfplanes(&points).into_iter().map(|s| s.points).collect();
+            assert_eq!(sweep, brute_force_halfplanes(&points), "{:?}", case);
+        }
+        let collinear = plane(&[(0., 0.), (1., 0.), (2., 0.)]);
+        assert_eq!(halfplanes(&collinear).len(), 6);
+    }
+
+    /// Sets of `ss` followed by its ranges, as bit sets
+    fn all_sets(ss: &SetSystem) -> Vec<BitSet> {
+        let sets = ss.sets.iter().map(|s| s.points.clone());
+        sets.chain(ss.ranges.iter().map(|r| r.to_bitset(&ss.points)))
+            .collect()
+    }
+
+    #[test]
+    fn text_formats_round_trip() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut ss = SetSystem::rhs(70, 10, 2, &mut rng);
+        ss.ranges = SetSystem::box_ranges(70, 4, 2, BoxShape::Uniform, &mut rng).ranges;
+        for format in [Format::Dense, Format::Sparse] {
+            let path = std::env::temp_dir().join(format!(
+                "partitions-rs-{}-{:?}.txt",
+                std::process::id(),
+                format
+            ));
+            let filename = path.to_str().expect("Temporary paths are UTF-8");
+            ss.to_file_as(filename, format);
+            let res = SetSystem::from_file(filename).expect("Written set systems can be read");
+            fs::remove_file(filename).expect("Fail to remove file");
+            assert_eq!(res.points.len(), ss.points.len());
+            assert_eq!(res.sets.len(), ss.sets.len(), "{:?}", format);
+            assert_eq!(res.ranges.len(), ss.ranges.len(), "{:?}", format);
+            assert_eq!(all_sets(&res), all_sets(&ss), "{:?}", format);
+        }
+    }
+
+    /// Set system read by `from_file` from a file holding `content`
+    fn parse(content: &str) -> Result<SetSystem, ParseError> {
+        //Tests run in parallel, so every call has its own file
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "partitions-rs-{}-parse-{}.txt",
+            std::process::id(),
+            CALLS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let filename = path.to_str().expect("Temporary paths are UTF-8");
+        fs::write(filename, content).expect("Fail to write");
+        let res = SetSystem::from_file(filename);
+        fs::remove_file(filename).expect("Fail to remove file");
+        res
+    }
+
+    /// Line, column and token of an `InvalidToken` error
+    fn invalid_token(res: Result<SetSystem, ParseError>) -> Option<(usize, usize, String)> {
+        match res {
+            Err(ParseError::InvalidToken {
+                line,
+                column,
+                token,
+            }) => Some((line, column, token)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn from_file_locates_bad_tokens() {
+        assert_eq!(
+            invalid_token(parse("0,1,\n0.5,x,\nsets\n")),
+            Some((2, 5, "x".to_string()))
+        );
+        assert_eq!(
+            invalid_token(parse("0,\n1,\nsets\n1,2,\n")),
+            Some((4, 3, "2".to_string()))
+        );
+        //Indices of sparse rows must be points of the file
+        assert_eq!(
+            invalid_token(parse("0,\n1,\nsets sparse\n+0,5,\n")),
+            Some((4, 4, "5".to_string()))
+        );
+        assert_eq!(
+            invalid_token(parse("0,\n1,\nsets sparse\n0,1,\n")),
+            Some((4, 1, "0".to_string()))
+        );
+    }
+
+    #[test]
+    fn from_file_rejects_inconsistent_rows() {
+        assert!(matches!(
+            parse("0,\n1,\nsets\n1,0,1,\n"),
+            Err(ParseError::RowLength {
+                line: 4,
+                expected: 2,
+                found: 3
+            })
+        ));
+        assert!(matches!(
+            parse("0,1,\n2,\nsets\n"),
+            Err(ParseError::Dimension {
+                line: 2,
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            parse("0,\n1,\n"),
+            Err(ParseError::MissingSeparator)
+        ));
+        let ss = parse("0,\n1,\nsets\n1,0,\nsets sparse\n-1,\n").expect("Valid file");
+        let sets: Vec<Vec<usize>> = ss.sets.iter().map(|s| s.points.ones().collect()).collect();
+        assert_eq!(sets, vec![vec![0]; 2]);
+    }
+}